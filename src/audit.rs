@@ -0,0 +1,102 @@
+//! A trail of display state transitions, each attributed to whatever last commanded
+//! the display, to find out afterwards why it ended up on or off.
+//! Kept in memory only, so it starts empty at every boot.
+
+use crate::task::display_state::DisplayState;
+use alloc::vec::Vec;
+use core::cell::RefCell;
+use critical_section::Mutex;
+use embassy_time::{Duration, Instant};
+use heapless::Deque;
+use serde::Serialize;
+
+// Number of most recent transitions kept.
+const AUDIT_TRAIL_LEN: usize = 16;
+/// Upper bound on the size of `history()` as JSON, for the buffer it's serialized into.
+pub const HISTORY_JSON_LEN: usize = {
+    // The longest entry, with a full-width `at_ms`, the longest state names and the
+    // longest source, followed by a comma:
+    // `{"at_ms":18446744073709551615,"from":"RelayLatchedFault",`
+    // `"to":"RelayLatchedFault","source":"PowerRestore"},`
+    const ENTRY_LEN: usize = 107;
+    // In `[]`.
+    2 + AUDIT_TRAIL_LEN * ENTRY_LEN
+};
+
+// Transitions this long after a command are no longer attributed to it.
+// Matches the longest a power sequence may run, a power cycle.
+const ATTRIBUTION_WINDOW: Duration = Duration::from_secs(60);
+
+static AUDIT: Mutex<RefCell<Audit>> = Mutex::new(RefCell::new(Audit::new()));
+
+/// Where a command to the display came from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub enum Source {
+    CaseButton,
+    Mqtt,
+    SerialTui,
+    UdpControl,
+    HostDisplay,
+    IdleOff,
+    HotPlug,
+    PowerRestore,
+    // The overtemperature, missing sensor and fan stall protections.
+    Protection,
+}
+
+#[derive(Clone, Copy, Debug, Serialize)]
+pub struct Transition {
+    pub at_ms: u64,
+    pub from: DisplayState,
+    pub to: DisplayState,
+    // None if nothing commanded the display recently, e.g. the board changed state on its own.
+    pub source: Option<Source>,
+}
+
+struct Audit {
+    transitions: Deque<Transition, AUDIT_TRAIL_LEN>,
+    last_command: Option<(Source, Instant)>,
+}
+
+impl Audit {
+    const fn new() -> Self {
+        Audit {
+            transitions: Deque::new(),
+            last_command: None,
+        }
+    }
+}
+
+/// Notes that `source` just commanded the display, relay or display-board buttons.
+pub fn commanded(source: Source) {
+    critical_section::with(|cs| {
+        AUDIT.borrow_ref_mut(cs).last_command = Some((source, Instant::now()));
+    });
+}
+
+/// Records a transition, attributed to the most recent command.
+pub fn transition(from: DisplayState, to: DisplayState) {
+    let now = Instant::now();
+    critical_section::with(|cs| {
+        let mut audit = AUDIT.borrow_ref_mut(cs);
+        let source = audit
+            .last_command
+            .filter(|&(_, at)| now - at <= ATTRIBUTION_WINDOW)
+            .map(|(source, _)| source);
+
+        if audit.transitions.is_full() {
+            audit.transitions.pop_front();
+        }
+        let _ = audit.transitions.push_back(Transition {
+            at_ms: now.as_millis(),
+            from,
+            to,
+            source,
+        });
+    });
+}
+
+/// The recorded transitions, oldest first.
+pub fn history() -> Vec<Transition> {
+    critical_section::with(|cs| AUDIT.borrow_ref(cs).transitions.iter().copied().collect())
+}
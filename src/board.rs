@@ -0,0 +1,114 @@
+//! Board definitions: which GPIO serves each role on the controller.
+//!
+//! One board is selected at build time with a `board-*` cargo feature. To add a
+//! board, add a feature and a `take_pins!` for it that fills the same `BoardPins`.
+//! The firmware assumes the display-board buttons and LEDs sit behind an MCP23009,
+//! so the older direct-wired pinouts in pinouts.rs can't be ported as they are.
+
+use esp_hal::gpio;
+
+#[cfg(not(feature = "board-xiao-esp32c6"))]
+compile_error!("select a board with a board-* feature");
+
+/// The board's pins by role, configured where the configuration doesn't belong to a task.
+pub struct BoardPins {
+    /// PWM signal to the fan. A high signal corresponds to 100% duty cycle.
+    pub fan_pwm: gpio::Output<'static>,
+    /// Fan tachometer input.
+    pub fan_tachy: gpio::Input<'static>,
+    /// 1-Wire bus for the DS18B20 temperature sensors, which are phantom-powered.
+    pub sensor_display_temp: gpio::AnyPin<'static>,
+    /// The video source's hot-plug detect line. High while connected.
+    pub sense_hpd: gpio::AnyPin<'static>,
+    /// Output of the 24V rail's current-sense amplifier. Must be an ADC1 channel.
+    pub sense_current: CurrentSensePin,
+    pub uart_tx: gpio::AnyPin<'static>,
+    pub uart_rx: gpio::AnyPin<'static>,
+    /// Drives the relay feeding the display controller board.
+    pub power_display_relay: gpio::Output<'static>,
+    pub buzzer: gpio::Output<'static>,
+    /// The case button, which pulls the line to GND when pressed.
+    pub button_case: gpio::AnyPin<'static>,
+    /// The MCP23009 I2C bus.
+    pub i2c_sda: gpio::AnyPin<'static>,
+    pub i2c_scl: gpio::AnyPin<'static>,
+    /// Pins that only need to hold their setup, e.g. the RF switch.
+    /// Kept here so they live as long as the pins in use.
+    pub held_outputs: [gpio::Output<'static>; 2],
+    pub held_inputs: [gpio::Input<'static>; 1],
+}
+
+#[cfg(feature = "board-xiao-esp32c6")]
+pub const BOARD_NAME: &str = "Seeed Studio XIAO ESP32C6";
+
+/// The ADC setup needs the concrete pin type, not an `AnyPin`.
+#[cfg(feature = "board-xiao-esp32c6")]
+pub type CurrentSensePin = esp_hal::peripherals::GPIO5<'static>;
+
+/// Takes the board's pins out of `esp_hal::Peripherals`.
+/// A macro rather than a function, so the other peripherals stay available to main.
+#[cfg(feature = "board-xiao-esp32c6")]
+macro_rules! take_pins {
+    ($peripherals:ident) => {{
+        use esp_hal::gpio;
+
+        // A default output config with a 5mA drive strength.
+        let output_5ma =
+            gpio::OutputConfig::default().with_drive_strength(gpio::DriveStrength::_5mA);
+
+        // Unused pins, taken here so they aren't used accidentally.
+        let _pin6_unused = $peripherals.GPIO6;
+        let _pin7_unused = $peripherals.GPIO7;
+        let _pin8_unused = $peripherals.GPIO8;
+        let _pin9_unused = $peripherals.GPIO9;
+        let _pin10_unused = $peripherals.GPIO10;
+        let _pin11_unused = $peripherals.GPIO11;
+        let _pin12_unused = $peripherals.GPIO12;
+        let _pin13_unused = $peripherals.GPIO13;
+        let _pin15_unused = $peripherals.GPIO15;
+
+        $crate::board::BoardPins {
+            // G0. Starts high, so the fan runs at full speed until controlled.
+            fan_pwm: gpio::Output::new($peripherals.GPIO0, gpio::Level::High, output_5ma),
+            // G1. The external pull-up and RC filter are on the board.
+            fan_tachy: gpio::Input::new(
+                $peripherals.GPIO1,
+                gpio::InputConfig::default().with_pull(gpio::Pull::None),
+            ),
+            // G2.
+            sensor_display_temp: $peripherals.GPIO2.into(),
+            // G4, through a divider.
+            sense_hpd: $peripherals.GPIO4.into(),
+            // G5, ADC1 channel 5.
+            sense_current: $peripherals.GPIO5,
+            uart_tx: $peripherals.GPIO16.into(),
+            uart_rx: $peripherals.GPIO17.into(),
+            // G18 drives the low-side MOSFET for the 24V relay coil.
+            power_display_relay: gpio::Output::new(
+                $peripherals.GPIO18,
+                gpio::Level::Low,
+                output_5ma,
+            ),
+            // G19.
+            buzzer: gpio::Output::new($peripherals.GPIO19, gpio::Level::Low, output_5ma),
+            // G20.
+            button_case: $peripherals.GPIO20.into(),
+            // G22/G23. Display-board buttons and LEDs live behind the MCP23009:
+            // GP0 green LED, GP1 red LED, GP2 power, GP3 up, GP4 down, GP5 enter, GP6 menu.
+            i2c_sda: $peripherals.GPIO22.into(),
+            i2c_scl: $peripherals.GPIO23.into(),
+            held_outputs: [
+                // G3+G14 drive the RF switch. Hold G3 low to enable switch control and G14
+                // low to select the onboard antenna (high would select the external U.FL one).
+                gpio::Output::new($peripherals.GPIO3, gpio::Level::Low, output_5ma),
+                gpio::Output::new($peripherals.GPIO14, gpio::Level::Low, output_5ma),
+            ],
+            held_inputs: [
+                // G21 is the MCP23009 interrupt pin. We poll for now, but reserve it.
+                gpio::Input::new($peripherals.GPIO21, gpio::InputConfig::default()),
+            ],
+        }
+    }};
+}
+#[cfg(feature = "board-xiao-esp32c6")]
+pub(crate) use take_pins;
@@ -0,0 +1,92 @@
+//! Rules shared by every control front-end (MQTT, UDP, the serial TUI), so a command
+//! that one of them rejects is rejected by all of them, for the same reason.
+
+use crate::{
+    metrics::METRICS,
+    task::{
+        display_control::{self, DisplayPowerDynSender, PowerRequest},
+        pin_control::{Button, ButtonPress, PinControlMessage, PinControlPublisher},
+        power_relay::RelayCommand,
+        safety,
+    },
+};
+
+/// Why a command was not carried out.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Rejected {
+    // Touching the power button or the relay now could cut power mid-sequence.
+    PowerSequenceBusy,
+    // pin_control still has a full queue of presses to work through.
+    ControllerBusy,
+    // The relay latch can't be reset until the display has cooled down.
+    TemperatureUnsafe,
+}
+
+impl Rejected {
+    /// A short description, for status lines and logs.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Rejected::PowerSequenceBusy => "power sequence in progress",
+            Rejected::ControllerBusy => "controller busy",
+            Rejected::TemperatureUnsafe => "temperature unsafe",
+        }
+    }
+}
+
+/// Checks whether a button press may be sent to the display board now.
+pub fn check_button(message: PinControlMessage) -> Result<(), Rejected> {
+    if message.button() == Button::Power && display_control::sequence_in_progress() {
+        return Err(Rejected::PowerSequenceBusy);
+    }
+    Ok(())
+}
+
+/// Queues a button press for pin_control without waiting for room,
+/// so a caller finds out at once that its press was turned away.
+pub fn send_button(
+    publisher: &PinControlPublisher,
+    message: PinControlMessage,
+) -> Result<(), Rejected> {
+    publisher
+        .try_publish(ButtonPress::new(message))
+        .map_err(|_full| {
+            METRICS.button_press_dropped();
+            Rejected::ControllerBusy
+        })
+}
+
+/// Checks whether a power request may be queued for display_control now.
+pub fn check_power(request: PowerRequest) -> Result<(), Rejected> {
+    // A shutdown is held until the running sequence completes.
+    if request != PowerRequest::Shutdown && display_control::sequence_in_progress() {
+        return Err(Rejected::PowerSequenceBusy);
+    }
+    Ok(())
+}
+
+/// Queues a power request for display_control without waiting for room.
+pub fn send_power(sender: &DisplayPowerDynSender, request: PowerRequest) -> Result<(), Rejected> {
+    sender
+        .try_send(request)
+        .map_err(|_full| Rejected::ControllerBusy)
+}
+
+/// Checks whether a relay command may be sent now.
+pub fn check_relay(command: RelayCommand) -> Result<(), Rejected> {
+    // Forcing the relay open stays available as an emergency stop.
+    if command != RelayCommand::ForceOpenLatch && display_control::sequence_in_progress() {
+        return Err(Rejected::PowerSequenceBusy);
+    }
+    if command == RelayCommand::ResetLatch {
+        check_latch_reset()?;
+    }
+    Ok(())
+}
+
+/// Checks whether a latched relay may be reset now.
+pub fn check_latch_reset() -> Result<(), Rejected> {
+    if !safety::temperature_safe() {
+        return Err(Rejected::TemperatureUnsafe);
+    }
+    Ok(())
+}
@@ -0,0 +1,105 @@
+//! Panic handler that prints esp-backtrace's backtrace, as its own handler would,
+//! and also keeps a crash report in RTC RAM across the reset that follows,
+//! so it can be read after reboot without a USB console attached. The supervisor
+//! uses the same report when it resets on a stalled task.
+//!
+//! RTC fast memory survives software and watchdog resets, but not a loss of power.
+
+use crate::memlog;
+use alloc::string::String;
+use core::{
+    fmt::{Display, Write},
+    panic::PanicInfo,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+const CRASH_LOG_MAGIC: u32 = 0xC7A5_4ED0;
+const CRASH_LOG_SIZE: usize = 1024;
+// How many of the most recent memlog records to include in the report.
+const CRASH_LOG_RECORDS: usize = 8;
+
+#[esp_hal::ram(unstable(rtc_fast, persistent))]
+static mut CRASH_LOG_HEADER: u32 = 0;
+#[esp_hal::ram(unstable(rtc_fast, persistent))]
+static mut CRASH_LOG_LEN: u32 = 0;
+#[esp_hal::ram(unstable(rtc_fast, persistent))]
+static mut CRASH_LOG_TEXT: [u8; CRASH_LOG_SIZE] = [0; CRASH_LOG_SIZE];
+
+// Set on the first panic, so a panic while writing the report goes straight to reset.
+static PANICKING: AtomicBool = AtomicBool::new(false);
+
+/// Takes the crash report left by the previous boot, if there is one.
+pub fn take() -> Option<String> {
+    // SAFETY: only called from main before tasks are spawned, and the report
+    // writers do not return, so nothing else accesses the crash log.
+    unsafe {
+        if CRASH_LOG_HEADER != CRASH_LOG_MAGIC {
+            return None;
+        }
+        CRASH_LOG_HEADER = 0;
+
+        let len = (CRASH_LOG_LEN as usize).min(CRASH_LOG_SIZE);
+        let text = &*core::ptr::addr_of!(CRASH_LOG_TEXT);
+        Some(String::from_utf8_lossy(&text[..len]).into_owned())
+    }
+}
+
+/// Writes into the crash log, truncating what doesn't fit.
+struct CrashLogWriter {
+    len: usize,
+}
+
+impl Write for CrashLogWriter {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        let count = s.len().min(CRASH_LOG_SIZE - self.len);
+        // SAFETY: only written right before a reset, by the panic handler (once, see
+        // PANICKING) or the supervisor, which has no await point in between.
+        let text = unsafe { &mut *core::ptr::addr_of_mut!(CRASH_LOG_TEXT) };
+        text[self.len..self.len + count].copy_from_slice(&s.as_bytes()[..count]);
+        self.len += count;
+        Ok(())
+    }
+}
+
+/// Records a crash report with `reason` and the most recent log records, then resets.
+pub fn reset_with_report(reason: impl Display) -> ! {
+    write_report(reason);
+    esp_hal::system::software_reset()
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    if PANICKING.swap(true, Ordering::Relaxed) {
+        esp_hal::system::software_reset();
+    }
+
+    // Same output as esp-backtrace's handler, for espflash to decode the addresses.
+    let backtrace = esp_backtrace::Backtrace::capture();
+    esp_println::println!("\n\n====================== PANIC ======================");
+    esp_println::println!("{info}");
+    esp_println::println!("\nBacktrace:\n");
+    for frame in backtrace.frames() {
+        esp_println::println!("0x{:x}", frame.program_counter());
+    }
+
+    write_report(info);
+
+    // Reset, as a power cycle would wipe the report.
+    esp_hal::system::software_reset()
+}
+
+fn write_report(reason: impl Display) {
+    // Avoid allocating from here on: the panic may be an allocation failure.
+    let mut writer = CrashLogWriter { len: 0 };
+    let _ = writeln!(writer, "{reason}");
+    memlog::with_recent_records(CRASH_LOG_RECORDS, |record| {
+        let uptime_ms = record.instant.as_millis();
+        let _ = writeln!(writer, "[{uptime_ms}] {}: {}", record.level, record.text);
+    });
+
+    // SAFETY: see CrashLogWriter.
+    unsafe {
+        CRASH_LOG_LEN = writer.len as u32;
+        CRASH_LOG_HEADER = CRASH_LOG_MAGIC;
+    }
+}
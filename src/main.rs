@@ -6,22 +6,30 @@
 
 extern crate alloc;
 
+mod audit;
+mod board;
+mod commands;
 mod config;
+mod crash;
 mod driver;
 mod ioexpander;
 mod kvconfig;
 mod kvstore;
 mod memlog;
+mod metrics;
+mod selftest;
+mod settings;
 mod task;
 
 use crate::ioexpander::IoExpander;
+use alloc::{boxed::Box, format};
 use embassy_executor::{SpawnError, Spawner};
 use esp_backtrace as _;
 use esp_hal::clock::CpuClock;
 use esp_hal::i2c::master::I2c;
 use esp_hal::time::Rate;
 use esp_hal::timer::timg::TimerGroup;
-use esp_hal::{gpio, i2c};
+use esp_hal::{i2c, ledc, tsens};
 
 // Default app-descriptor required by the esp-idf bootloader.
 esp_bootloader_esp_idf::esp_app_desc!();
@@ -47,6 +55,8 @@ async fn main(spawner: Spawner) {
         esp_hal::interrupt::software::SoftwareInterruptControl::new(peripherals.SW_INTERRUPT);
     esp_rtos::start(timg0.timer0, sw_interrupt.software_interrupt0);
     let rng = esp_hal::rng::Rng::new();
+    // TIMG1's watchdog backs the task supervisor.
+    let timg1 = TimerGroup::new(peripherals.TIMG1);
 
     // Initialize an in-memory logger with space for 480 characters.
     let memlog = memlog::init(480);
@@ -54,60 +64,23 @@ async fn main(spawner: Spawner) {
     memlog.info("init: imac5k display controller");
     memlog.info("init: hardware initialized");
 
-    //
-    // XIAO ESP32C6 pinout
-    //
-
-    // A default output config with a 5mA drive strength.
-    let output_5ma = gpio::OutputConfig::default().with_drive_strength(gpio::DriveStrength::_5mA);
-
-    // G0 sends a PWM signal to the fan. A high signal corresponds to 100% duty cycle.
-    let pin_fan_pwm = gpio::Output::new(peripherals.GPIO0, gpio::Level::High, output_5ma);
-    // G1 reads the fan tachometer. The external pull-up and RC filter are on the board.
-    let pin_fan_tachy = gpio::Input::new(
-        peripherals.GPIO1,
-        gpio::InputConfig::default().with_pull(gpio::Pull::None),
-    );
-    // G2 is the 1Wire bus commanding the DS18B20 temperature sensors, which are phantom-powered.
-    let pin_sensor_display_temp = peripherals.GPIO2;
-    // G3+G14 drive the RF switch. Hold G3 low to enable switch control and G14 low to select
-    // the onboard antenna (high would select the external U.FL antenna).
-    let _pin_rf_switch_ctrl = gpio::Output::new(peripherals.GPIO3, gpio::Level::Low, output_5ma);
-    let _pin4_unused = peripherals.GPIO4;
-    let _pin5_unused = peripherals.GPIO5;
-    let _pin6_unused = peripherals.GPIO6;
-    let _pin7_unused = peripherals.GPIO7;
-    let _pin8_unused = peripherals.GPIO8;
-    let _pin9_unused = peripherals.GPIO9;
-    let _pin10_unused = peripherals.GPIO10;
-    let _pin11_unused = peripherals.GPIO11;
-    let _pin12_unused = peripherals.GPIO12;
-    let _pin13_unused = peripherals.GPIO13;
-    // Antenna selection (see G3).
-    let _pin_antenna_sel = gpio::Output::new(peripherals.GPIO14, gpio::Level::Low, output_5ma);
-    let _pin15_unused = peripherals.GPIO15;
-    // UART pins.
-    let pin_uart_tx = peripherals.GPIO16;
-    let pin_uart_rx = peripherals.GPIO17;
-    // G18 drives the low-side MOSFET for the 24V relay coil feeding the display controller board.
-    let pin_power_display_relay =
-        gpio::Output::new(peripherals.GPIO18, gpio::Level::Low, output_5ma);
-    // G19 controls the buzzer.
-    let pin_buzzer = gpio::Output::new(
-        peripherals.GPIO19,
-        gpio::Level::Low,
-        gpio::OutputConfig::default().with_drive_strength(gpio::DriveStrength::_5mA),
-    );
-    // G20 reads the case button, which pulls the line to GND when pressed.
-    let pin_button_case = peripherals.GPIO20;
-    // G21 is the MCP23009 interrupt pin. We will poll initially, but reserve it now.
-    let _pin_io_expander_int = gpio::Input::new(peripherals.GPIO21, gpio::InputConfig::default());
-    // G22/G23 carry the MCP23009 I2C bus.
-    let pin_i2c_sda = peripherals.GPIO22;
-    let pin_i2c_scl = peripherals.GPIO23;
-    // Display-board buttons and LEDs live behind the MCP23009.
-    // GP0 green LED, GP1 red LED, GP2 power, GP3 up, GP4 down, GP5 enter, GP6 menu.
-
+    // Pick up the report left by a panic on the previous boot.
+    let last_crash = crash::take();
+    if last_crash.is_some() {
+        memlog.error("init: recovered from a crash, report on mqtt");
+    }
+
+    // Open the settings kept in flash. Without them, every setting keeps its default.
+    let settings = settings::init(peripherals.FLASH).unwrap_or_else(|error| {
+        memlog.error(format!("init: settings unavailable: {error:?}"));
+        settings::Settings::unavailable()
+    });
+    if let Some(capacity) = settings.load::<usize>(settings::LOG_CAPACITY) {
+        memlog.set_capacity(capacity.min(memlog::MAX_CAPACITY));
+    }
+
+    // Take the pins for each role from the selected board definition.
+    let pins = board::take_pins!(peripherals);
     memlog.info("init: board pinout configured");
 
     //
@@ -118,8 +91,8 @@ async fn main(spawner: Spawner) {
     let i2c_config = i2c::master::Config::default().with_frequency(Rate::from_khz(400));
     let i2c_master = I2c::new(peripherals.I2C0, i2c_config)
         .unwrap()
-        .with_sda(pin_i2c_sda)
-        .with_scl(pin_i2c_scl);
+        .with_sda(pins.i2c_sda)
+        .with_scl(pins.i2c_scl);
 
     // Initialize the IO expander.
     let mcp23009 = driver::mcp23009::Mcp23009::new(i2c_master);
@@ -129,60 +102,118 @@ async fn main(spawner: Spawner) {
     let (wifi_controller, wifi_interfaces) = task::wifi::init(peripherals.WIFI).await.unwrap();
 
     // Set up the network stack.
-    let (net_stack, net_runner) = task::net::init(wifi_interfaces.station, rng).await;
+    let (net_stack, net_runner) = task::net::init(wifi_interfaces.station, rng, settings).await;
+
+    // LED Controller (LEDC) PWM setup, shared by the fan and the buzzer.
+    let mut ledc = ledc::Ledc::new(peripherals.LEDC);
+    ledc.set_global_slow_clock(ledc::LSGlobalClkSource::APBClk);
+    let ledc: &'static ledc::Ledc<'static> = Box::leak(Box::new(ledc));
 
-    // Get a shareable channel to send buzzer control messages.
-    let buzzer_channel = task::buzzer::init();
+    // Set up the buzzer, and get a shareable channel to send it control messages.
+    let (buzzer, buzzer_channel, buzzermute_watch) = task::buzzer::init::<1>(ledc, pins.buzzer);
 
     // Get a watch to know when the case button has been pressed.
     let casebutton_watch = task::case_button::init::<2>();
 
     // Get a shareable channel to send messages to the pincontrol task.
-    let (pincontrol_pubsub, displayled_watch) = task::pin_control::init::<4, 3, 3>();
+    let (pincontrol_pubsub, displayled_watch, buttonack_pubsub) =
+        task::pin_control::init::<4, 3, 3, 2>();
 
     // Init the fan duty PWM controller.
     let (pwm_channel, fanduty_watch, fantachy_watch) =
-        task::fan_control::init::<4>(peripherals.LEDC, pin_fan_pwm);
+        task::fan_control::init::<5>(ledc, pins.fan_pwm);
+
+    // Get a watcher for the fan stall state.
+    let fanstall_watch = task::fan_stall::init::<1>();
 
     // Get a watcher to await changes in temperature sensor readings.
-    let tempsensor_watch = task::temp_sensor::init::<5>();
+    let tempsensor_watch = task::temp_sensor::init::<6>();
 
     // Get a watcher to monitor the network interface.
-    let netstatus_watch = task::net_monitor::init::<3>();
+    let (netstatus_watch, netinterval_watch) = task::net_monitor::init::<3>(settings);
 
     // Get a command channel and state watcher for the display-controller power relay.
     let (powerrelay_channel, powerrelay_watch) = task::power_relay::init::<4, 3>();
 
+    // Get a channel to request OSD button macros.
+    let osd_channel = task::osd::init::<2>();
+
+    // Queue ping requests for the network diagnostics task.
+    let netping_channel = task::net_ping::init::<1>();
+
     // Get a watcher for the consolidated display-board state.
-    let displayboard_watch = task::display_state::init::<4>();
+    let displayboard_watch = task::display_state::init::<6>();
+
+    // Get a channel to request display power changes.
+    let (displaypower_channel, sequencetimings_watch) = task::display_control::init::<4>(settings);
+
+    // Get a watcher for the host computer's display sleep state.
+    let hostdisplay_watch = task::host_display::init::<1>();
+
+    // Get a watcher for the periodic activity digest.
+    let digest_watch = task::digest::init::<1>();
+
+    // Get a watcher for the idle power-off settings.
+    let idleconfig_watch = task::idle_off::init::<1>(settings);
+
+    // Get a watcher for the hot-plug detect settings.
+    let hpdconfig_watch = task::hpd_monitor::init::<1>(settings);
+
+    // Get a watcher for the host presence settings.
+    let presenceconfig_watch = task::host_presence::init::<1>(settings);
 
     // WRITEME
     let (control_signal, event_channel) = task::serial_tui::init();
 
-    // // Set up the internal temperature sensor.
-    // let _onboard_sensor =
-    //     tsens::TemperatureSensor::new(peripherals.TSENS, tsens::Config::default()).unwrap();
+    // Set up the internal temperature sensor, and get a watcher for its readings.
+    let chip_temp_sensor =
+        tsens::TemperatureSensor::new(peripherals.TSENS, tsens::Config::default()).unwrap();
+    let chiptemp_watch = task::chip_temp::init::<1>();
+
+    // Get a watcher for the display's power draw.
+    let powerdraw_watch = task::power_monitor::init::<2>();
 
     memlog.info("init: tasks initialized");
 
     //
     // Spawn tasks.
     || -> Result<(), SpawnError> {
+        // Reset if a critical task stalls.
+        spawner.spawn(task::supervisor(timg1.wdt, memlog)?);
+
+        // Shed load if the heap runs low.
+        spawner.spawn(task::heap_monitor(memlog)?);
+
         // Run the buzzer controller.
-        spawner.spawn(task::buzzer_control(pin_buzzer, buzzer_channel)?);
+        spawner.spawn(task::buzzer_control(
+            buzzer,
+            buzzer_channel,
+            buzzermute_watch.dyn_receiver().unwrap(),
+        )?);
 
         // Control the display-board buttons behind the MCP23009 and watch the board LEDs.
         spawner.spawn(task::pin_control(
             ioexpander,
             pincontrol_pubsub.dyn_subscriber().unwrap(),
             displayled_watch.dyn_sender(),
+            buttonack_pubsub.dyn_immediate_publisher(),
+            powerrelay_watch.dyn_receiver().unwrap(),
             buzzer_channel,
             memlog,
         )?);
 
+        // Replay OSD button macros on request.
+        spawner.spawn(task::osd_macro(
+            osd_channel.dyn_receiver(),
+            pincontrol_pubsub.dyn_publisher().unwrap(),
+            buttonack_pubsub.dyn_subscriber().unwrap(),
+            memlog,
+        )?);
+
         // Keep the wifi connected.
         spawner.spawn(task::wifi::wifi_permanent_connection(
             wifi_controller,
+            displayboard_watch.dyn_receiver().unwrap(),
             memlog,
         )?);
 
@@ -190,11 +221,41 @@ async fn main(spawner: Spawner) {
         spawner.spawn(task::net::stack_runner(net_runner)?);
 
         // Monitor the network stack for changes.
-        spawner.spawn(task::net_monitor(net_stack, netstatus_watch.dyn_sender())?);
+        spawner.spawn(task::net_monitor(
+            net_stack,
+            netinterval_watch.dyn_receiver().unwrap(),
+            netstatus_watch.dyn_sender(),
+        )?);
+
+        // Forward logs to a remote syslog server.
+        spawner.spawn(task::syslog(net_stack, memlog)?);
+
+        // Accept encrypted power requests over UDP.
+        spawner.spawn(task::udp_control(
+            net_stack,
+            displaypower_channel.dyn_sender(),
+            settings,
+            memlog,
+        )?);
+
+        // Follow the host computer's presence on the network.
+        spawner.spawn(task::host_presence(
+            net_stack,
+            presenceconfig_watch.dyn_receiver().unwrap(),
+            hostdisplay_watch.dyn_sender(),
+            memlog,
+        )?);
+
+        // Ping addresses on request, for network diagnostics.
+        spawner.spawn(task::net_ping(
+            net_stack,
+            netping_channel.dyn_receiver(),
+            memlog,
+        )?);
 
         // Operate the display-controller power relay.
         spawner.spawn(task::power_relay(
-            pin_power_display_relay,
+            pins.power_display_relay,
             powerrelay_channel.dyn_receiver(),
             powerrelay_watch.dyn_sender(),
         )?);
@@ -210,16 +271,44 @@ async fn main(spawner: Spawner) {
         // Handle power-on and power-off sequences on command.
         spawner.spawn(task::display_control(
             casebutton_watch.dyn_receiver().unwrap(),
+            displaypower_channel.dyn_receiver(),
+            sequencetimings_watch.dyn_receiver().unwrap(),
             displayboard_watch.dyn_receiver().unwrap(),
+            powerdraw_watch.dyn_receiver().unwrap(),
             pincontrol_pubsub.dyn_publisher().unwrap(),
+            buttonack_pubsub.dyn_subscriber().unwrap(),
             powerrelay_channel.dyn_sender(),
             buzzer_channel,
+            settings,
+            memlog,
+        )?);
+
+        // Mirror the host computer's display sleep onto the display.
+        spawner.spawn(task::host_display_follow(
+            hostdisplay_watch.dyn_receiver().unwrap(),
+            displaypower_channel.dyn_sender(),
+            memlog,
+        )?);
+
+        // Power the display off when left without a signal.
+        spawner.spawn(task::idle_power_off(
+            idleconfig_watch.dyn_receiver().unwrap(),
+            displayboard_watch.dyn_receiver().unwrap(),
+            displaypower_channel.dyn_sender(),
+            memlog,
+        )?);
+
+        // Follow the video source's hot-plug detect line.
+        spawner.spawn(task::hpd_monitor(
+            pins.sense_hpd,
+            hpdconfig_watch.dyn_receiver().unwrap(),
+            displaypower_channel.dyn_sender(),
             memlog,
         )?);
 
         // Watch the case button for presses.
         spawner.spawn(task::case_button(
-            pin_button_case.into(),
+            pins.button_case,
             casebutton_watch.dyn_sender(),
             buzzer_channel,
             memlog,
@@ -232,18 +321,51 @@ async fn main(spawner: Spawner) {
         )?);
 
         // Read the fan tachometer periodically.
-        spawner.spawn(task::fan_tachy(pin_fan_tachy, fantachy_watch.dyn_sender())?);
+        spawner.spawn(task::fan_tachy(
+            pins.fan_tachy,
+            fantachy_watch.dyn_sender(),
+        )?);
+
+        // Alarm if the fan stops while commanded on.
+        spawner.spawn(task::fan_stall_monitor(
+            fanduty_watch.dyn_receiver().unwrap(),
+            fantachy_watch.dyn_receiver().unwrap(),
+            fanstall_watch.dyn_sender(),
+            displaypower_channel.dyn_sender(),
+            buzzer_channel,
+            memlog,
+        )?);
 
         // Take a temperature measurement periodically.
         spawner.spawn(task::temp_sensor(
-            pin_sensor_display_temp.into(),
+            pins.sensor_display_temp,
             tempsensor_watch.dyn_sender(),
+            buzzer_channel,
+            memlog,
         )?);
 
+        // Read the SoC die temperature periodically.
+        spawner.spawn(task::chip_temp(
+            chip_temp_sensor,
+            chiptemp_watch.dyn_sender(),
+        )?);
+
+        // Measure the display's power draw, if a current sensor is fitted.
+        if task::power_monitor::CURRENT_SENSOR_FITTED {
+            spawner.spawn(task::power_monitor(
+                peripherals.ADC1,
+                pins.sense_current,
+                powerdraw_watch.dyn_sender(),
+            )?);
+        }
+
         // Keep adjusting the fan duty based on the temperature measurements.
         spawner.spawn(task::fan_temp_control(
             fanduty_watch.dyn_sender(),
             tempsensor_watch.dyn_receiver().unwrap(),
+            displayboard_watch.dyn_receiver().unwrap(),
+            buzzer_channel,
+            memlog,
         )?);
 
         // Hardware safety watchdog.
@@ -256,6 +378,18 @@ async fn main(spawner: Spawner) {
             memlog,
         )?);
 
+        // Keep a history of temperature readings.
+        spawner.spawn(task::telemetry(tempsensor_watch.dyn_receiver().unwrap())?);
+
+        // Summarize activity periodically.
+        spawner.spawn(task::digest(
+            tempsensor_watch.dyn_receiver().unwrap(),
+            fanduty_watch.dyn_receiver().unwrap(),
+            netstatus_watch.dyn_receiver().unwrap(),
+            digest_watch.dyn_sender(),
+            memlog,
+        )?);
+
         // Spawn the MQTT control task.
         spawner.spawn(task::mqtt::run(
             net_stack,
@@ -263,8 +397,25 @@ async fn main(spawner: Spawner) {
             fantachy_watch.dyn_receiver().unwrap(),
             pincontrol_pubsub.dyn_publisher().unwrap(),
             pincontrol_pubsub.dyn_subscriber().unwrap(),
+            displaypower_channel.dyn_sender(),
+            osd_channel.dyn_sender(),
+            hostdisplay_watch.dyn_sender(),
+            idleconfig_watch.dyn_sender(),
+            hpdconfig_watch.dyn_sender(),
+            presenceconfig_watch.dyn_sender(),
+            sequencetimings_watch.dyn_sender(),
+            buzzermute_watch.dyn_sender(),
+            netinterval_watch.dyn_sender(),
+            netping_channel.dyn_sender(),
+            buzzer_channel,
+            settings,
+            last_crash,
             netstatus_watch.dyn_receiver().unwrap(),
+            fanstall_watch.dyn_receiver().unwrap(),
             tempsensor_watch.dyn_receiver().unwrap(),
+            chiptemp_watch.dyn_receiver().unwrap(),
+            powerdraw_watch.dyn_receiver().unwrap(),
+            digest_watch.dyn_receiver().unwrap(),
             displayboard_watch.dyn_receiver().unwrap(),
             memlog,
         )?);
@@ -286,8 +437,8 @@ async fn main(spawner: Spawner) {
         // Launch the UART control interface.
         spawner.spawn(task::serial_tui::run(
             peripherals.UART0.into(),
-            pin_uart_rx.into(),
-            pin_uart_tx.into(),
+            pins.uart_rx,
+            pins.uart_tx,
             pincontrol_pubsub.dyn_publisher().unwrap(),
             fanduty_watch.dyn_sender(),
             powerrelay_channel.dyn_sender(),
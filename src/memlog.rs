@@ -1,14 +1,31 @@
 //! An in-memory log storage, with a fixed size for records.
 #![allow(dead_code)]
 
-use alloc::{boxed::Box, collections::vec_deque::VecDeque, format, string::String};
-use core::{cell::RefCell, fmt::Display};
+use alloc::{
+    boxed::Box,
+    collections::vec_deque::VecDeque,
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::{
+    cell::RefCell,
+    fmt::Display,
+    sync::atomic::{AtomicPtr, Ordering},
+};
 use embassy_sync::{blocking_mutex::raw::NoopRawMutex, watch};
 use embassy_time::Instant;
+use serde::{Deserialize, Serialize};
 
-const MEMLOG_WATCHERS: usize = 2;
+const MEMLOG_WATCHERS: usize = 3;
+/// Largest capacity that can be set at runtime, in characters. Records are kept on
+/// the heap, which the network stack and power sequences also need.
+pub const MAX_CAPACITY: usize = 8 * 1024;
 const DISCARD_ERROR: &str = "log discarded: too large for storage";
 
+// The log storage, for the panic handler to read records from.
+static PANIC_STORAGE: AtomicPtr<RefCell<LogStorage>> = AtomicPtr::new(core::ptr::null_mut());
+
 #[derive(Clone, Copy)]
 pub struct SharedLogger {
     inner: &'static RefCell<LogStorage>,
@@ -23,8 +40,28 @@ pub fn init(capacity: usize) -> SharedLogger {
     }
 
     let storage = LogStorage::with_capacity(capacity);
-    SharedLogger {
-        inner: Box::leak(Box::new(RefCell::new(storage))),
+    let inner = Box::leak(Box::new(RefCell::new(storage)));
+    PANIC_STORAGE.store(inner, Ordering::Relaxed);
+
+    SharedLogger { inner }
+}
+
+/// Calls `f` on up to `count` of the most recent records, oldest first.
+///
+/// For use from the panic handler, which has no SharedLogger to go through.
+pub fn with_recent_records(count: usize, mut f: impl FnMut(&Record)) {
+    // SAFETY: the pointer is either null or points to the leaked storage from init().
+    let Some(storage) = (unsafe { PANIC_STORAGE.load(Ordering::Relaxed).as_ref() }) else {
+        return;
+    };
+
+    // The panic may have struck while the storage was borrowed.
+    let Ok(storage) = storage.try_borrow() else {
+        return;
+    };
+
+    for record in storage.records.iter().take(count).rev() {
+        f(record);
     }
 }
 
@@ -37,15 +74,55 @@ struct LogStorage {
     print: bool,
     // If set, broadcasts new records over the watch channel.
     watch: Option<&'static watch::Watch<NoopRawMutex, Record, MEMLOG_WATCHERS>>,
+    // Stops broadcasting records, while keeping the watch channel.
+    watch_paused: bool,
+    // Records below this level are discarded.
+    min_level: Level,
+    // Per-tag overrides of `min_level`, keyed on the "tag:" prefix of a record.
+    tag_levels: Vec<(String, Level)>,
+    // Sequence number for the next record.
+    next_seq: u32,
+    // Records removed to make room for newer ones.
+    evicted: u32,
+    // Records that did not fit in storage at all.
+    discarded: u32,
+    // Warn and Error records stored.
+    warnings: u32,
+}
+
+/// Storage counters, for clients to detect gaps in the records they received.
+#[derive(Clone, Copy, Debug, Serialize)]
+pub struct LogStats {
+    pub next_seq: u32,
+    pub evicted: u32,
+    pub discarded: u32,
+    pub warnings: u32,
+    pub utilization: usize,
+    pub capacity: usize,
 }
 
 #[derive(Clone, Debug)]
 pub struct Record {
+    // Increases by one with every stored record.
+    pub seq: u32,
     pub instant: Instant,
     pub level: Level,
     pub text: String,
 }
 
+impl Record {
+    /// The task or subsystem that logged this record, from its "tag: message" prefix.
+    pub fn tag(&self) -> Option<&str> {
+        record_tag(&self.text)
+    }
+
+    /// Whether this record was stored at or after the moment `next_seq` was taken
+    /// from `LogStats`. Handles the sequence number wrapping around.
+    pub fn is_since(&self, next_seq: u32) -> bool {
+        self.seq.wrapping_sub(next_seq) < u32::MAX / 2
+    }
+}
+
 impl Display for Record {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         let timestamp = format_milliseconds_to_hms(self.instant.as_millis());
@@ -53,7 +130,7 @@ impl Display for Record {
     }
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
 pub enum Level {
     Trace,
     Debug,
@@ -82,14 +159,27 @@ impl LogStorage {
             capacity,
             print: false,
             watch: None,
+            watch_paused: false,
+            min_level: Level::Trace,
+            tag_levels: Vec::new(),
+            next_seq: 0,
+            evicted: 0,
+            discarded: 0,
+            warnings: 0,
         }
     }
 
     fn add_record(&mut self, level: Level, text: impl Into<String>) {
         let text: String = text.into();
 
+        // Drop records filtered out by level.
+        if level < self.level_for(&text) {
+            return;
+        }
+
         // Can't fit this record in storage. Log a warning.
         if text.len() > self.capacity {
+            self.discarded = self.discarded.wrapping_add(1);
             self.add_record(Level::Warn, DISCARD_ERROR);
             return;
         }
@@ -98,18 +188,20 @@ impl LogStorage {
         // records need to be removed), so we can safely use unwraps.
 
         // Pop existing records until we have enough space for the new record.
-        while (self.capacity - self.utilization) < text.len() {
-            let removed = self.records.pop_back().unwrap();
-            self.utilization -= removed.text.len();
-        }
+        self.evict_until(self.capacity - text.len());
 
         self.utilization += text.len();
+        if level >= Level::Warn {
+            self.warnings = self.warnings.wrapping_add(1);
+        }
 
         let new_record = Record {
+            seq: self.next_seq,
             instant: Instant::now(),
             level,
             text,
         };
+        self.next_seq = self.next_seq.wrapping_add(1);
 
         // If log printing is enabled, print this record.
         if self.print {
@@ -117,7 +209,9 @@ impl LogStorage {
         }
 
         // If log watching is enabled, share this record.
-        if let Some(watch) = self.watch {
+        if let Some(watch) = self.watch
+            && !self.watch_paused
+        {
             watch.sender().send(new_record.clone());
         }
 
@@ -125,10 +219,74 @@ impl LogStorage {
         self.records.push_front(new_record);
     }
 
+    // Pops the oldest records until utilization is at most `utilization`.
+    fn evict_until(&mut self, utilization: usize) {
+        while self.utilization > utilization {
+            let removed = self.records.pop_back().unwrap();
+            self.utilization -= removed.text.len();
+            self.evicted = self.evicted.wrapping_add(1);
+        }
+    }
+
     fn clear(&mut self) {
         self.utilization = 0;
         self.records.clear();
     }
+
+    // The minimum level for a record, given its text.
+    fn level_for(&self, text: &str) -> Level {
+        record_tag(text)
+            .and_then(|tag| self.tag_levels.iter().find(|(known, _)| known == tag))
+            .map_or(self.min_level, |&(_, level)| level)
+    }
+}
+
+/// Checks eviction and discarding on a scratch storage, for the on-device self-test.
+pub fn storage_self_test() -> bool {
+    let record = "x".repeat(30);
+    let mut storage = LogStorage::with_capacity(64);
+
+    // The third record evicts the first.
+    for _ in 0..3 {
+        storage.add_record(Level::Info, record.as_str());
+    }
+    let evicts = storage.records.len() == 2 && storage.evicted == 1 && storage.utilization == 60;
+
+    // An oversized record is discarded, and the warning about it evicts both others.
+    storage.add_record(Level::Info, "x".repeat(70));
+    let discards = storage.discarded == 1 && storage.evicted == 3 && storage.records.len() == 1;
+
+    evicts && discards
+}
+
+/// Checks that resuming from `LogStats.next_seq` picks up new records only,
+/// as syslog does when it starts forwarding.
+pub fn resume_self_test() -> bool {
+    let mut storage = LogStorage::with_capacity(64);
+    storage.add_record(Level::Info, "first");
+    storage.add_record(Level::Info, "second");
+    let next_seq = storage.next_seq;
+
+    let resends = storage
+        .records
+        .iter()
+        .any(|record| record.is_since(next_seq));
+
+    storage.add_record(Level::Info, "third");
+    let since: Vec<_> = storage
+        .records
+        .iter()
+        .filter(|record| record.is_since(next_seq))
+        .collect();
+    let picks_up_new = since.len() == 1 && since[0].text == "third";
+
+    !resends && picks_up_new
+}
+
+// Extracts the "tag" from a "tag: message" record.
+fn record_tag(text: &str) -> Option<&str> {
+    let (tag, _message) = text.split_once(':')?;
+    (!tag.contains(' ')).then_some(tag)
 }
 
 impl SharedLogger {
@@ -136,6 +294,43 @@ impl SharedLogger {
         self.inner.borrow_mut().print = true;
     }
 
+    pub fn disable_print(&self) {
+        self.inner.borrow_mut().print = false;
+    }
+
+    /// Sets the minimum level of records to keep.
+    pub fn set_level(&self, level: Level) {
+        self.inner.borrow_mut().min_level = level;
+    }
+
+    /// Sets the minimum level for records with a given tag, or removes
+    /// the override if `level` is None.
+    pub fn set_tag_level(&self, tag: &str, level: Option<Level>) {
+        let mut inner = self.inner.borrow_mut();
+        inner.tag_levels.retain(|(known, _)| known != tag);
+        if let Some(level) = level {
+            inner.tag_levels.push((tag.to_string(), level));
+        }
+    }
+
+    /// Changes the storage capacity, evicting old records to fit.
+    pub fn set_capacity(&self, capacity: usize) {
+        let capacity = capacity.max(DISCARD_ERROR.len());
+        let mut inner = self.inner.borrow_mut();
+        inner.capacity = capacity;
+        inner.evict_until(capacity);
+        inner.records.shrink_to_fit();
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.inner.borrow().capacity
+    }
+
+    /// Pauses or resumes broadcasting records to watchers.
+    pub fn pause_watch(&self, paused: bool) {
+        self.inner.borrow_mut().watch_paused = paused;
+    }
+
     pub fn enable_watch(&self) {
         let mut inner = self.inner.borrow_mut();
         if inner.watch.is_none() {
@@ -174,6 +369,17 @@ impl SharedLogger {
     pub fn records(&self) -> core::cell::Ref<'_, VecDeque<Record>> {
         core::cell::Ref::map(self.inner.borrow(), |storage| &storage.records)
     }
+    pub fn stats(&self) -> LogStats {
+        let inner = self.inner.borrow();
+        LogStats {
+            next_seq: inner.next_seq,
+            evicted: inner.evicted,
+            discarded: inner.discarded,
+            warnings: inner.warnings,
+            utilization: inner.utilization,
+            capacity: inner.capacity,
+        }
+    }
 }
 
 /// Formats a u64 millisecond value into "HHHHH:MM:SS.xxx" string.
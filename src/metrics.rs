@@ -0,0 +1,199 @@
+//! Actuation counters, to track wear on the display board's switches and the buzzer,
+//! 1-Wire bus error counts, and latencies of control actions.
+//! Everything starts from zero at every boot.
+
+use crate::task::pin_control::Button;
+use core::{
+    cell::RefCell,
+    sync::atomic::{AtomicU32, Ordering},
+};
+use critical_section::Mutex;
+use embassy_time::Duration;
+use serde::Serialize;
+
+// Number of most recent samples kept for each latency.
+const LATENCY_WINDOW: usize = 32;
+
+pub static METRICS: Metrics = Metrics::new();
+
+pub struct Metrics {
+    // Indexed by `Button as usize`.
+    button_presses: [AtomicU32; 5],
+    // Presses turned away because pin_control's queue was full.
+    button_presses_dropped: AtomicU32,
+    buzzer_patterns: AtomicU32,
+    power_sequences: AtomicU32,
+    power_sequences_failed: AtomicU32,
+    onewire_checksum_failures: AtomicU32,
+    onewire_errors: AtomicU32,
+    // Lowest free heap seen by the heap monitor's sampling.
+    heap_free_low_water: AtomicU32,
+    // From publishing a power button press to its acknowledgement by pin_control.
+    power_press_latency: Mutex<RefCell<LatencyWindow>>,
+    // From starting a power sequence to reaching the target state.
+    power_sequence_latency: Mutex<RefCell<LatencyWindow>>,
+}
+
+/// A point-in-time copy of the counters.
+#[derive(Clone, Copy, Debug, Serialize)]
+pub struct MetricsSnapshot {
+    pub power_button: u32,
+    pub menu_button: u32,
+    pub back_button: u32,
+    pub down_button: u32,
+    pub up_button: u32,
+    pub button_presses_dropped: u32,
+    pub buzzer_patterns: u32,
+    pub power_sequences: u32,
+    pub power_sequences_failed: u32,
+    pub onewire_checksum_failures: u32,
+    pub onewire_errors: u32,
+    pub power_press_latency: LatencySummary,
+    pub power_sequence_latency: LatencySummary,
+}
+
+/// Percentiles over the most recent latency samples.
+#[derive(Clone, Copy, Debug, Default, Serialize)]
+pub struct LatencySummary {
+    pub samples: usize,
+    pub p50_ms: u32,
+    pub p95_ms: u32,
+    pub max_ms: u32,
+}
+
+struct LatencyWindow {
+    samples_ms: [u32; LATENCY_WINDOW],
+    len: usize,
+    next: usize,
+}
+
+impl LatencyWindow {
+    const fn new() -> Self {
+        LatencyWindow {
+            samples_ms: [0; LATENCY_WINDOW],
+            len: 0,
+            next: 0,
+        }
+    }
+
+    fn record(&mut self, latency: Duration) {
+        self.samples_ms[self.next] = latency.as_millis().min(u32::MAX as u64) as u32;
+        self.next = (self.next + 1) % LATENCY_WINDOW;
+        self.len = (self.len + 1).min(LATENCY_WINDOW);
+    }
+
+    fn summary(&self) -> LatencySummary {
+        if self.len == 0 {
+            return LatencySummary::default();
+        }
+
+        let mut sorted = self.samples_ms;
+        let sorted = &mut sorted[..self.len];
+        sorted.sort_unstable();
+        let percentile = |pct: usize| sorted[(self.len - 1) * pct / 100];
+
+        LatencySummary {
+            samples: self.len,
+            p50_ms: percentile(50),
+            p95_ms: percentile(95),
+            max_ms: sorted[self.len - 1],
+        }
+    }
+}
+
+impl Metrics {
+    const fn new() -> Self {
+        Metrics {
+            button_presses: [const { AtomicU32::new(0) }; 5],
+            button_presses_dropped: AtomicU32::new(0),
+            buzzer_patterns: AtomicU32::new(0),
+            power_sequences: AtomicU32::new(0),
+            power_sequences_failed: AtomicU32::new(0),
+            onewire_checksum_failures: AtomicU32::new(0),
+            onewire_errors: AtomicU32::new(0),
+            heap_free_low_water: AtomicU32::new(u32::MAX),
+            power_press_latency: Mutex::new(RefCell::new(LatencyWindow::new())),
+            power_sequence_latency: Mutex::new(RefCell::new(LatencyWindow::new())),
+        }
+    }
+
+    pub fn button_pressed(&self, button: Button) {
+        self.button_presses[button as usize].fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn button_press_dropped(&self) {
+        self.button_presses_dropped.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn buzzer_pattern_played(&self) {
+        self.buzzer_patterns.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn power_sequence_started(&self) {
+        self.power_sequences.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn power_sequence_failed(&self) {
+        self.power_sequences_failed.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Counts a 1-Wire read that failed its checksum, retried or not.
+    pub fn onewire_checksum_failed(&self) {
+        self.onewire_checksum_failures
+            .fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Counts a sensor read that failed for any reason other than a checksum.
+    pub fn onewire_failed(&self) {
+        self.onewire_errors.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn heap_free_sampled(&self, free: usize) {
+        self.heap_free_low_water
+            .fetch_min(free as u32, Ordering::Relaxed);
+    }
+
+    /// The lowest free heap sampled so far, if any.
+    pub fn heap_free_low_water(&self) -> Option<usize> {
+        match self.heap_free_low_water.load(Ordering::Relaxed) {
+            u32::MAX => None,
+            free => Some(free as usize),
+        }
+    }
+
+    pub fn power_press_acknowledged(&self, latency: Duration) {
+        critical_section::with(|cs| self.power_press_latency.borrow_ref_mut(cs).record(latency));
+    }
+
+    pub fn power_sequence_finished(&self, latency: Duration) {
+        critical_section::with(|cs| {
+            self.power_sequence_latency
+                .borrow_ref_mut(cs)
+                .record(latency)
+        });
+    }
+
+    pub fn snapshot(&self) -> MetricsSnapshot {
+        let presses = |button: Button| self.button_presses[button as usize].load(Ordering::Relaxed);
+
+        MetricsSnapshot {
+            power_button: presses(Button::Power),
+            menu_button: presses(Button::Menu),
+            back_button: presses(Button::Back),
+            down_button: presses(Button::Down),
+            up_button: presses(Button::Up),
+            button_presses_dropped: self.button_presses_dropped.load(Ordering::Relaxed),
+            buzzer_patterns: self.buzzer_patterns.load(Ordering::Relaxed),
+            power_sequences: self.power_sequences.load(Ordering::Relaxed),
+            power_sequences_failed: self.power_sequences_failed.load(Ordering::Relaxed),
+            onewire_checksum_failures: self.onewire_checksum_failures.load(Ordering::Relaxed),
+            onewire_errors: self.onewire_errors.load(Ordering::Relaxed),
+            power_press_latency: critical_section::with(|cs| {
+                self.power_press_latency.borrow_ref(cs).summary()
+            }),
+            power_sequence_latency: critical_section::with(|cs| {
+                self.power_sequence_latency.borrow_ref(cs).summary()
+            }),
+        }
+    }
+}
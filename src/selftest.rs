@@ -0,0 +1,58 @@
+//! On-device smoke tests, for checking a board before it goes behind the panel.
+//! Results are logged as a TAP report under the "selftest" tag.
+//!
+//! There is no UART loopback test. The serial TUI owns UART0, and a loopback on UART1
+//! needs a TX to RX jumper on two pins that board.rs doesn't assign. There is no PWM
+//! set/readback test either, as esp-hal's LEDC driver can set a duty but has no way to
+//! read it back.
+
+use crate::memlog::{self, SharedLogger};
+use alloc::{format, vec::Vec};
+use embassy_sync::{blocking_mutex::raw::NoopRawMutex, watch};
+
+const TESTS: &[(&str, fn() -> bool)] = &[
+    ("heap allocation", heap_allocation),
+    ("watch round-trip", watch_round_trip),
+    ("memlog fill and evict", memlog::storage_self_test),
+    ("memlog resume without resending", memlog::resume_self_test),
+];
+
+/// Runs every test and logs the report. Returns whether all passed.
+pub fn run(memlog: SharedLogger) -> bool {
+    memlog.info(format!("selftest: 1..{}", TESTS.len()));
+
+    let mut passed = 0;
+    for (index, (name, test)) in TESTS.iter().enumerate() {
+        let number = index + 1;
+        if test() {
+            passed += 1;
+            memlog.info(format!("selftest: ok {number} - {name}"));
+        } else {
+            memlog.error(format!("selftest: not ok {number} - {name}"));
+        }
+    }
+
+    memlog.info(format!("selftest: # passed {passed}/{}", TESTS.len()));
+    passed == TESTS.len()
+}
+
+fn heap_allocation() -> bool {
+    // Other tasks allocate concurrently, so free heap before and after won't match.
+    let mut buffer: Vec<u8> = Vec::new();
+    if buffer.try_reserve_exact(4096).is_err() {
+        return false;
+    }
+    buffer.extend((0..=255).cycle().take(4096));
+
+    buffer.iter().enumerate().all(|(i, &byte)| byte == i as u8)
+}
+
+fn watch_round_trip() -> bool {
+    let watch = watch::Watch::<NoopRawMutex, u32, 1>::new();
+    let Some(mut receiver) = watch.receiver() else {
+        return false;
+    };
+
+    watch.sender().send(0xC0FFEE);
+    receiver.try_changed() == Some(0xC0FFEE) && receiver.try_changed().is_none()
+}
@@ -0,0 +1,194 @@
+//! Settings that survive a reboot, kept in a sector of flash.
+//!
+//! Each setting is stored as JSON under its own key. Every change rewrites the whole
+//! sector, so a store that didn't change the value is skipped. Settings that can't be
+//! read (never stored, or the sector is blank or corrupt) fall back to their defaults
+//! in code.
+
+use alloc::{boxed::Box, string::String, vec, vec::Vec};
+use core::cell::RefCell;
+use embedded_storage::{ReadStorage, Storage};
+use esp_hal::peripherals::FLASH;
+use esp_storage::{FlashStorage, FlashStorageError};
+use serde::{Serialize, de::DeserializeOwned};
+
+/// `display_control::PowerRestorePolicy`.
+pub const POWER_RESTORE_POLICY: &str = "power_restore";
+/// Whether the display was last commanded on, for `PowerRestorePolicy::RestoreLast`.
+pub const LAST_POWER_STATE: &str = "last_power";
+/// `display_control::SequenceTimings`.
+pub const SEQUENCE_TIMINGS: &str = "seq_timings";
+/// `idle_off::IdleConfig`.
+pub const IDLE_CONFIG: &str = "idle";
+/// `hpd_monitor::HpdConfig`.
+pub const HPD_CONFIG: &str = "hpd";
+/// `host_presence::PresenceConfig`.
+pub const PRESENCE_CONFIG: &str = "presence";
+/// `net_monitor` poll interval, in seconds.
+pub const NET_INTERVAL: &str = "net_interval";
+/// `net::StaticV4`, replacing the IPv4 part of `config::NET_CONFIG`.
+pub const STATIC_V4: &str = "static_v4";
+/// Display temperature deadband for MQTT, in degrees Celsius.
+pub const TEMP_DEADBAND: &str = "temp_deadband";
+/// Memlog capacity, in characters.
+pub const LOG_CAPACITY: &str = "log_capacity";
+/// Highest packet counter accepted by `udp_control`.
+pub const UDP_CONTROL_COUNTER: &str = "udp_counter";
+
+// The first sector of the `nvs` partition in the default partition table. Nothing
+// else in this firmware uses NVS.
+const SECTOR_OFFSET: u32 = 0x9000;
+const SECTOR_SIZE: usize = 4096;
+const MAGIC: [u8; 4] = *b"i5ks";
+// Magic, payload length (u16) and payload checksum (u32).
+const HEADER_LEN: usize = 10;
+// Largest value, as JSON.
+const MAX_VALUE_LEN: usize = 256;
+
+/// Why a setting was not stored.
+#[derive(Debug)]
+pub enum StoreError {
+    /// The flash could not be read at boot.
+    Unavailable,
+    Flash(FlashStorageError),
+    /// The value failed to serialize, or is longer than `MAX_VALUE_LEN`.
+    Encode,
+    /// All settings together no longer fit in the sector.
+    Full,
+}
+
+#[derive(Clone, Copy)]
+pub struct Settings {
+    // None if the flash could not be read.
+    inner: Option<&'static RefCell<Store>>,
+}
+
+struct Store {
+    flash: FlashStorage<'static>,
+    // Key and JSON value of each stored setting, as last written to flash.
+    entries: Vec<(String, Vec<u8>)>,
+}
+
+pub fn init(flash: FLASH<'static>) -> Result<Settings, FlashStorageError> {
+    let mut flash = FlashStorage::new(flash);
+    let mut sector = vec![0u8; SECTOR_SIZE];
+    flash.read(SECTOR_OFFSET, &mut sector)?;
+
+    // A blank (never written) or corrupt sector holds no settings.
+    let entries = decode(&sector).unwrap_or_default();
+    let inner = Box::leak(Box::new(RefCell::new(Store { flash, entries })));
+
+    Ok(Settings { inner: Some(inner) })
+}
+
+impl Settings {
+    /// Settings that always read as unset and can't be stored, for when the
+    /// flash could not be read.
+    pub fn unavailable() -> Self {
+        Settings { inner: None }
+    }
+
+    /// Reads a setting, if it was stored and still decodes.
+    pub fn load<T: DeserializeOwned>(&self, key: &str) -> Option<T> {
+        let store = self.inner?.borrow();
+        let (_, value) = store.entries.iter().find(|(known, _)| known == key)?;
+        serde_json_core::from_slice(value)
+            .ok()
+            .map(|(value, _remainder)| value)
+    }
+
+    pub fn store<T: Serialize>(&self, key: &str, value: &T) -> Result<(), StoreError> {
+        let inner = self.inner.ok_or(StoreError::Unavailable)?;
+        let mut buffer = [0u8; MAX_VALUE_LEN];
+        let len = serde_json_core::to_slice(value, &mut buffer).map_err(|_| StoreError::Encode)?;
+        let value = &buffer[..len];
+
+        let mut store = inner.borrow_mut();
+        let mut entries = store.entries.clone();
+        match entries.iter_mut().find(|(known, _)| known == key) {
+            // Save the flash the wear.
+            Some((_, stored)) if stored.as_slice() == value => return Ok(()),
+            Some((_, stored)) => *stored = value.to_vec(),
+            None => entries.push((String::from(key), value.to_vec())),
+        }
+
+        store.write(entries)
+    }
+
+    /// Erases every stored setting, so all of them read back as their defaults.
+    pub fn clear(&self) -> Result<(), StoreError> {
+        let inner = self.inner.ok_or(StoreError::Unavailable)?;
+        inner.borrow_mut().write(Vec::new())
+    }
+}
+
+impl Store {
+    // Writes `entries` to flash, and keeps them once written.
+    fn write(&mut self, entries: Vec<(String, Vec<u8>)>) -> Result<(), StoreError> {
+        let sector = encode(&entries)?;
+        self.flash
+            .write(SECTOR_OFFSET, &sector)
+            .map_err(StoreError::Flash)?;
+        self.entries = entries;
+
+        Ok(())
+    }
+}
+
+// Entries are laid out as `key length (u8) | key | value length (u16) | value`.
+fn encode(entries: &[(String, Vec<u8>)]) -> Result<Vec<u8>, StoreError> {
+    let mut payload = Vec::new();
+    for (key, value) in entries {
+        payload.push(key.len() as u8);
+        payload.extend_from_slice(key.as_bytes());
+        payload.extend_from_slice(&(value.len() as u16).to_le_bytes());
+        payload.extend_from_slice(value);
+    }
+    if HEADER_LEN + payload.len() > SECTOR_SIZE {
+        return Err(StoreError::Full);
+    }
+
+    let mut sector = Vec::with_capacity(HEADER_LEN + payload.len());
+    sector.extend_from_slice(&MAGIC);
+    sector.extend_from_slice(&(payload.len() as u16).to_le_bytes());
+    sector.extend_from_slice(&checksum(&payload).to_le_bytes());
+    sector.extend_from_slice(&payload);
+
+    Ok(sector)
+}
+
+fn decode(sector: &[u8]) -> Option<Vec<(String, Vec<u8>)>> {
+    let (header, body) = sector.split_at_checked(HEADER_LEN)?;
+    if header[..4] != MAGIC {
+        return None;
+    }
+    let len = u16::from_le_bytes([header[4], header[5]]) as usize;
+    let mut payload = body.get(..len)?;
+    // A write cut short by a reset leaves a payload that doesn't match.
+    if checksum(payload) != u32::from_le_bytes([header[6], header[7], header[8], header[9]]) {
+        return None;
+    }
+
+    let mut entries = Vec::new();
+    while let [key_len, rest @ ..] = payload {
+        let (key, rest) = rest.split_at_checked(*key_len as usize)?;
+        let (value_len, rest) = rest.split_at_checked(2)?;
+        let value_len = u16::from_le_bytes([value_len[0], value_len[1]]) as usize;
+        let (value, rest) = rest.split_at_checked(value_len)?;
+
+        entries.push((
+            String::from(core::str::from_utf8(key).ok()?),
+            value.to_vec(),
+        ));
+        payload = rest;
+    }
+
+    Some(entries)
+}
+
+// 32-bit FNV-1a.
+fn checksum(bytes: &[u8]) -> u32 {
+    bytes.iter().fold(0x811c_9dc5, |hash, &byte| {
+        (hash ^ byte as u32).wrapping_mul(0x0100_0193)
+    })
+}
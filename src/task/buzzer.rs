@@ -1,46 +1,254 @@
-use alloc::boxed::Box;
-use embassy_sync::{blocking_mutex::raw::NoopRawMutex, channel};
+use crate::metrics::METRICS;
+use alloc::{boxed::Box, vec::Vec};
+use embassy_sync::{blocking_mutex::raw::NoopRawMutex, channel, watch};
 use embassy_time::Timer;
-use esp_hal::gpio;
+use esp_hal::{
+    gpio,
+    ledc::{self, LowSpeed, channel::ChannelIFace, timer::TimerIFace},
+    time,
+};
+use serde::{Deserialize, Serialize};
 
 const CHANNEL_BACKLOG: usize = 5;
 
+// LEDC resources reserved for the buzzer. Timer0/Channel0 drive the fan.
+const BUZZER_TIMER: ledc::timer::Number = ledc::timer::Number::Timer1;
+const BUZZER_CHANNEL: ledc::channel::Number = ledc::channel::Number::Channel1;
+
+// Tone frequencies are clamped to what a 10-bit LEDC timer can reach from the APB clock.
+const TONE_MIN_HZ: u32 = 100;
+const TONE_MAX_HZ: u32 = 10_000;
+const TONE_DEFAULT_HZ: u32 = 2_000;
+
+// Limits on patterns supplied at runtime.
+const CUSTOM_PATTERN_MAX_STEPS: usize = 16;
+const CUSTOM_PATTERN_MAX_STEP_MS: u32 = 2_000;
+
+const BOOT_PATTERN: &[BuzzerAction] = &[BuzzerAction::Beep { ms: 100 }];
+const ACK_PATTERN: &[BuzzerAction] = &[
+    BuzzerAction::Beep { ms: 100 },
+    BuzzerAction::Pause { ms: 50 },
+    BuzzerAction::Beep { ms: 100 },
+];
+const LONG_PRESS_PATTERN: &[BuzzerAction] = &[
+    BuzzerAction::Beep { ms: 320 },
+    BuzzerAction::Pause { ms: 100 },
+];
+const ERROR_PATTERN: &[BuzzerAction] = &[
+    BuzzerAction::tone(600, 120),
+    BuzzerAction::Pause { ms: 80 },
+    BuzzerAction::tone(400, 350),
+];
+const TIMEOUT_PATTERN: &[BuzzerAction] = &[
+    BuzzerAction::Beep { ms: 320 },
+    BuzzerAction::Pause { ms: 100 },
+    BuzzerAction::Beep { ms: 100 },
+    BuzzerAction::Pause { ms: 100 },
+    BuzzerAction::Beep { ms: 100 },
+    BuzzerAction::Pause { ms: 100 },
+    BuzzerAction::Beep { ms: 100 },
+];
+// A rising chime.
+const POWER_ON_PATTERN: &[BuzzerAction] = &[
+    BuzzerAction::tone(1500, 80),
+    BuzzerAction::tone(2000, 80),
+    BuzzerAction::tone(3000, 120),
+];
+// A two-tone siren, distinct from every other pattern.
+const ALARM_PATTERN: &[BuzzerAction] = &[
+    BuzzerAction::tone(3200, 250),
+    BuzzerAction::tone(2400, 250),
+    BuzzerAction::tone(3200, 250),
+    BuzzerAction::tone(2400, 250),
+    BuzzerAction::tone(3200, 250),
+    BuzzerAction::tone(2400, 250),
+];
+
 pub type BuzzerChannel = &'static channel::Channel<NoopRawMutex, BuzzerPattern, CHANNEL_BACKLOG>;
-pub type BuzzerPattern = &'static [BuzzerAction];
+
+/// A pattern to play, either from the registry or supplied at runtime.
+#[derive(Clone, Debug)]
+pub enum BuzzerPattern {
+    Named(NamedPattern),
+    Custom(Vec<BuzzerAction>),
+}
+
+/// Registry of patterns that tasks refer to by name.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum NamedPattern {
+    Boot,
+    Ack,
+    LongPress,
+    Error,
+    Timeout,
+    PowerOn,
+    Alarm,
+}
+
+pub type BuzzerMuteWatch<const W: usize> = &'static watch::Watch<NoopRawMutex, bool, W>;
+pub type BuzzerMuteDynSender = watch::DynSender<'static, bool>;
+pub type BuzzerMuteDynReceiver = watch::DynReceiver<'static, bool>;
 
 #[derive(Clone, Copy, Debug)]
 pub enum BuzzerAction {
+    // Drives the buzzer continuously, sounding at its own resonant pitch.
     Beep { ms: u32 },
+    // Drives the buzzer with a square wave at the given frequency.
+    Tone { freq_hz: u32, ms: u32 },
     Pause { ms: u32 },
 }
 
-pub fn init() -> BuzzerChannel {
-    Box::leak(Box::new(channel::Channel::new()))
+impl BuzzerAction {
+    /// Shorthand for a `Tone`, to keep patterns compact.
+    pub const fn tone(freq_hz: u32, ms: u32) -> Self {
+        BuzzerAction::Tone { freq_hz, ms }
+    }
 }
 
-/// Plays patterns on the buzzer pin.
+impl NamedPattern {
+    fn actions(self) -> &'static [BuzzerAction] {
+        match self {
+            NamedPattern::Boot => BOOT_PATTERN,
+            NamedPattern::Ack => ACK_PATTERN,
+            NamedPattern::LongPress => LONG_PRESS_PATTERN,
+            NamedPattern::Error => ERROR_PATTERN,
+            NamedPattern::Timeout => TIMEOUT_PATTERN,
+            NamedPattern::PowerOn => POWER_ON_PATTERN,
+            NamedPattern::Alarm => ALARM_PATTERN,
+        }
+    }
+}
+
+impl From<NamedPattern> for BuzzerPattern {
+    fn from(named: NamedPattern) -> Self {
+        BuzzerPattern::Named(named)
+    }
+}
+
+impl BuzzerPattern {
+    fn actions(&self) -> &[BuzzerAction] {
+        match self {
+            BuzzerPattern::Named(named) => named.actions(),
+            BuzzerPattern::Custom(actions) => actions,
+        }
+    }
+
+    /// Parses a comma-separated list of durations in milliseconds into
+    /// alternating beeps and pauses, e.g. `100,50,100`.
+    pub fn parse_durations(text: &str) -> Option<Self> {
+        let mut actions = Vec::new();
+
+        for (index, field) in text.split(',').enumerate() {
+            let ms: u32 = field.trim().parse().ok()?;
+            if index >= CUSTOM_PATTERN_MAX_STEPS || ms > CUSTOM_PATTERN_MAX_STEP_MS {
+                return None;
+            }
+
+            actions.push(match index % 2 {
+                0 => BuzzerAction::Beep { ms },
+                _ => BuzzerAction::Pause { ms },
+            });
+        }
+
+        Some(BuzzerPattern::Custom(actions))
+    }
+}
+
+/// The buzzer pin, driven by an LEDC channel.
+pub struct Buzzer {
+    ledc: &'static ledc::Ledc<'static>,
+    pwm_channel: ledc::channel::Channel<'static, LowSpeed>,
+}
+
+impl Buzzer {
+    fn set_frequency(&self, freq_hz: u32) -> Result<(), ledc::timer::Error> {
+        // Reconfiguring a fresh handle to the same hardware timer retunes the
+        // channel, which keeps its borrow of the timer it was configured with.
+        let mut timer = self.ledc.timer::<LowSpeed>(BUZZER_TIMER);
+        timer.configure(timer_config(freq_hz))
+    }
+
+    fn set_duty(&self, duty_pct: u8) {
+        self.pwm_channel.set_duty(duty_pct).unwrap(); // Does not fail for duty ∈ [0,100]
+    }
+}
+
+fn timer_config(freq_hz: u32) -> ledc::timer::config::Config<ledc::timer::LSClockSource> {
+    ledc::timer::config::Config {
+        duty: ledc::timer::config::Duty::Duty10Bit,
+        clock_source: ledc::timer::LSClockSource::APBClk,
+        frequency: time::Rate::from_hz(freq_hz.clamp(TONE_MIN_HZ, TONE_MAX_HZ)),
+    }
+}
+
+/// Sets up the buzzer on an LEDC channel, to be passed to the buzzer_control task.
+#[must_use]
+pub fn init<const WATCHERS: usize>(
+    ledc: &'static ledc::Ledc<'static>,
+    pin_buzzer: gpio::Output<'static>,
+) -> (Buzzer, BuzzerChannel, BuzzerMuteWatch<WATCHERS>) {
+    // The timer needs to be 'static for the LEDC channel to also be 'static.
+    let mut lstimer1 = ledc.timer::<LowSpeed>(BUZZER_TIMER);
+    lstimer1.configure(timer_config(TONE_DEFAULT_HZ)).unwrap();
+    let lstimer1 = Box::leak(Box::new(lstimer1));
+
+    let mut ledc_channel1 = ledc.channel(BUZZER_CHANNEL, pin_buzzer);
+    ledc_channel1
+        .configure(ledc::channel::config::Config {
+            timer: lstimer1,
+            duty_pct: 0,
+            drive_mode: esp_hal::gpio::DriveMode::PushPull,
+        })
+        .unwrap();
+
+    let buzzer = Buzzer {
+        ledc,
+        pwm_channel: ledc_channel1,
+    };
+    let buzzer_channel = Box::leak(Box::new(channel::Channel::new()));
+    let buzzermute_watch = Box::leak(Box::new(watch::Watch::new_with(false)));
+
+    (buzzer, buzzer_channel, buzzermute_watch)
+}
+
+/// Plays patterns on the buzzer pin, unless muted.
 #[embassy_executor::task]
-pub async fn buzzer_control(mut pin_buzzer: gpio::Output<'static>, buzzer_channel: BuzzerChannel) {
+pub async fn buzzer_control(
+    buzzer: Buzzer,
+    buzzer_channel: BuzzerChannel,
+    mut buzzermute_receiver: BuzzerMuteDynReceiver,
+) {
     // Queue a pattern on buzzer init.
-    buzzer_channel
-        .send([BuzzerAction::Beep { ms: 100 }].as_ref())
-        .await;
-
-    pin_buzzer.set_low();
+    buzzer_channel.send(NamedPattern::Boot.into()).await;
 
     loop {
         let pattern = buzzer_channel.receive().await;
-        for step in pattern.iter() {
+
+        // Patterns sent while muted are dropped, not deferred.
+        if buzzermute_receiver.try_get() == Some(true) {
+            continue;
+        }
+
+        METRICS.buzzer_pattern_played();
+        for step in pattern.actions() {
             match step {
                 BuzzerAction::Beep { ms } => {
-                    pin_buzzer.set_high();
+                    buzzer.set_duty(100);
+                    Timer::after_millis(*ms as u64).await;
+                    buzzer.set_duty(0);
+                }
+                BuzzerAction::Tone { freq_hz, ms } => {
+                    // An unreachable frequency skips the tone, but keeps the pattern's timing.
+                    if buzzer.set_frequency(*freq_hz).is_ok() {
+                        buzzer.set_duty(50);
+                    }
                     Timer::after_millis(*ms as u64).await;
-                    pin_buzzer.set_low();
+                    buzzer.set_duty(0);
                 }
                 BuzzerAction::Pause { ms } => Timer::after_millis(*ms as u64).await,
             }
         }
-        pin_buzzer.set_low();
+        buzzer.set_duty(0);
 
         // Min 1 second pause between any consecutive beep sequences.
         Timer::after_secs(1).await;
@@ -1,6 +1,6 @@
 use crate::{
     memlog::SharedLogger,
-    task::buzzer::{BuzzerAction, BuzzerChannel, BuzzerPattern},
+    task::buzzer::{BuzzerChannel, NamedPattern},
 };
 use alloc::boxed::Box;
 use embassy_sync::{blocking_mutex::raw::NoopRawMutex, watch};
@@ -9,22 +9,15 @@ use esp_hal::gpio;
 
 const SHORT_PRESS_MIN_DURATION: Duration = Duration::from_millis(1000);
 const LONG_PRESS_MIN_DURATION: Duration = Duration::from_millis(4000);
-
-// TODO: move this to our state machine task
-const CASE_BUTTON_SHORT_PRESS_PATTERN: BuzzerPattern = &[
-    BuzzerAction::Beep { ms: 100 },
-    BuzzerAction::Pause { ms: 50 },
-    BuzzerAction::Beep { ms: 100 },
-];
-const CASE_BUTTON_LONG_PRESS_PATTERN: BuzzerPattern = &[
-    BuzzerAction::Beep { ms: 320 },
-    BuzzerAction::Pause { ms: 100 },
-];
+// Held on past a long press, which has already cut power, to power the display back on
+// through a power cycle.
+const VERY_LONG_PRESS_MIN_DURATION: Duration = Duration::from_millis(10000);
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum CaseButton {
     ShortPress,
     LongPress,
+    VeryLongPress,
 }
 
 pub type CaseButtonWatch<const W: usize> = &'static watch::Watch<NoopRawMutex, CaseButton, W>;
@@ -60,7 +53,7 @@ pub async fn case_button(
         }
 
         // Button is held for a short press.
-        buzzer_channel.send(CASE_BUTTON_SHORT_PRESS_PATTERN).await;
+        buzzer_channel.send(NamedPattern::Ack.into()).await;
 
         let long_press_remaining = LONG_PRESS_MIN_DURATION - SHORT_PRESS_MIN_DURATION;
         if embassy_time::with_timeout(long_press_remaining, case_pin.wait_for_high())
@@ -74,8 +67,22 @@ pub async fn case_button(
         }
 
         // Button was held for a long press.
-        buzzer_channel.send(CASE_BUTTON_LONG_PRESS_PATTERN).await;
+        buzzer_channel.send(NamedPattern::LongPress.into()).await;
         casebutton_sender.send(CaseButton::LongPress);
         memlog.info("case: long button press");
+
+        let very_long_press_remaining = VERY_LONG_PRESS_MIN_DURATION - LONG_PRESS_MIN_DURATION;
+        if embassy_time::with_timeout(very_long_press_remaining, case_pin.wait_for_high())
+            .await
+            .is_ok()
+        {
+            // Button is released, leaving the long press.
+            continue;
+        }
+
+        // Button was held on for a very long press.
+        buzzer_channel.send(NamedPattern::Ack.into()).await;
+        casebutton_sender.send(CaseButton::VeryLongPress);
+        memlog.info("case: very long button press");
     }
 }
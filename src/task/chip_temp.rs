@@ -0,0 +1,27 @@
+use alloc::boxed::Box;
+use embassy_sync::{blocking_mutex::raw::NoopRawMutex, watch};
+use embassy_time::{Duration, Timer};
+use esp_hal::tsens::TemperatureSensor;
+
+/// How often to read the SoC die temperature.
+const CHIP_TEMP_INTERVAL: Duration = Duration::from_secs(10);
+
+pub type ChipTempWatch<const W: usize> = &'static watch::Watch<NoopRawMutex, f32, W>;
+pub type ChipTempDynSender = watch::DynSender<'static, f32>;
+pub type ChipTempDynReceiver = watch::DynReceiver<'static, f32>;
+
+/// Takes a const that sets the maximum number of watchers.
+pub fn init<const WATCHERS: usize>() -> ChipTempWatch<WATCHERS> {
+    Box::leak(Box::new(watch::Watch::new()))
+}
+
+/// Periodically reads the SoC's internal temperature sensor, in degrees Celsius.
+/// Independent of the 1-Wire bus, so it keeps reporting if the DS18B20 fails.
+#[embassy_executor::task]
+pub async fn chip_temp(tsens: TemperatureSensor<'static>, chiptemp_sender: ChipTempDynSender) {
+    loop {
+        Timer::after(CHIP_TEMP_INTERVAL).await;
+
+        chiptemp_sender.send(tsens.get_temperature().to_celsius());
+    }
+}
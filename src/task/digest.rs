@@ -0,0 +1,120 @@
+use crate::{
+    memlog::SharedLogger,
+    metrics::METRICS,
+    task::{
+        fan_control::FanDutyDynReceiver, net_monitor::NetStatusDynReceiver,
+        temp_sensor::TempSensorDynReceiver,
+    },
+};
+use alloc::boxed::Box;
+use embassy_futures::select::{Either4, select4};
+use embassy_sync::{blocking_mutex::raw::NoopRawMutex, watch};
+use embassy_time::{Duration, Instant, Timer};
+use serde::Serialize;
+
+// How often to summarize. Set to None to disable the digest.
+const DIGEST_INTERVAL: Option<Duration> = Some(Duration::from_secs(24 * 60 * 60));
+
+/// A summary of the controller's activity over one digest interval.
+#[derive(Clone, Copy, Debug, Default, Serialize)]
+pub struct Digest {
+    pub period_s: u64,
+    pub temp_min_c: Option<f32>,
+    pub temp_max_c: Option<f32>,
+    pub fan_on_s: u64,
+    pub power_sequences: u32,
+    pub power_sequences_failed: u32,
+    pub wifi_drops: u32,
+    pub warnings: u32,
+}
+
+pub type DigestWatch<const W: usize> = &'static watch::Watch<NoopRawMutex, Digest, W>;
+pub type DigestDynSender = watch::DynSender<'static, Digest>;
+pub type DigestDynReceiver = watch::DynReceiver<'static, Digest>;
+
+/// Takes a const that sets the maximum number of watchers.
+pub fn init<const WATCHERS: usize>() -> DigestWatch<WATCHERS> {
+    Box::leak(Box::new(watch::Watch::new()))
+}
+
+/// Periodically summarizes temperatures, fan run time, power sequences, wifi drops
+/// and logged warnings, so quiet failures get noticed.
+#[embassy_executor::task]
+pub async fn digest(
+    mut tempsensor_receiver: TempSensorDynReceiver,
+    mut fanduty_receiver: FanDutyDynReceiver,
+    mut netstatus_receiver: NetStatusDynReceiver,
+    digest_sender: DigestDynSender,
+    memlog: SharedLogger,
+) {
+    let Some(interval) = DIGEST_INTERVAL else {
+        return;
+    };
+
+    loop {
+        let started_at = Instant::now();
+        let metrics_at_start = METRICS.snapshot();
+        let warnings_at_start = memlog.stats().warnings;
+
+        let mut digest = Digest::default();
+        let mut fan_on_since = (fanduty_receiver.try_get().unwrap_or(0) > 0).then_some(started_at);
+        let mut fan_on_time = Duration::from_secs(0);
+        let mut link_up = netstatus_receiver.try_get().map(|status| status.link_up);
+
+        let summary_at = started_at + interval;
+        loop {
+            match select4(
+                tempsensor_receiver.changed(),
+                fanduty_receiver.changed(),
+                netstatus_receiver.changed(),
+                Timer::at(summary_at),
+            )
+            .await
+            {
+                Either4::First(reading) => {
+                    if let Ok(temp_c) = reading.temperature {
+                        digest.temp_min_c =
+                            Some(digest.temp_min_c.map_or(temp_c, |t| t.min(temp_c)));
+                        digest.temp_max_c =
+                            Some(digest.temp_max_c.map_or(temp_c, |t| t.max(temp_c)));
+                    }
+                }
+
+                Either4::Second(duty) => match (fan_on_since, duty > 0) {
+                    (None, true) => fan_on_since = Some(Instant::now()),
+                    (Some(since), false) => {
+                        fan_on_time += since.elapsed();
+                        fan_on_since = None;
+                    }
+                    _ => (),
+                },
+
+                Either4::Third(status) => {
+                    if link_up == Some(true) && !status.link_up {
+                        digest.wifi_drops += 1;
+                    }
+                    link_up = Some(status.link_up);
+                }
+
+                Either4::Fourth(()) => break,
+            }
+        }
+
+        if let Some(since) = fan_on_since {
+            fan_on_time += since.elapsed();
+        }
+
+        let metrics = METRICS.snapshot();
+        digest.period_s = started_at.elapsed().as_secs();
+        digest.fan_on_s = fan_on_time.as_secs();
+        digest.power_sequences = metrics
+            .power_sequences
+            .wrapping_sub(metrics_at_start.power_sequences);
+        digest.power_sequences_failed = metrics
+            .power_sequences_failed
+            .wrapping_sub(metrics_at_start.power_sequences_failed);
+        digest.warnings = memlog.stats().warnings.wrapping_sub(warnings_at_start);
+
+        digest_sender.send(digest);
+    }
+}
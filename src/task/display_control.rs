@@ -1,95 +1,291 @@
 use crate::{
+    audit::{self, Source},
+    commands,
     memlog::SharedLogger,
+    metrics::METRICS,
+    settings::{self, Settings},
     task::{
-        buzzer::{BuzzerAction, BuzzerChannel, BuzzerPattern},
+        buzzer::{BuzzerChannel, NamedPattern},
         case_button::{CaseButton, CaseButtonDynReceiver},
         display_state::{DisplayState, DisplayStateDynReceiver},
-        pin_control::{PinControlMessage, PinControlPublisher},
+        pin_control::{
+            self, ButtonAck, ButtonAckSubscriber, ButtonPress, PinControlMessage,
+            PinControlPublisher, PressError,
+        },
+        power_monitor::PowerDrawDynReceiver,
         power_relay::{PowerRelayDynSender, RelayCommand},
     },
 };
 use alloc::{boxed::Box, format};
-use core::{future::Future, pin::Pin};
+use core::{
+    future::Future,
+    pin::Pin,
+    sync::atomic::{AtomicBool, Ordering},
+};
 use embassy_futures::select::{Either, select};
-use embassy_time::{Duration, Timer, with_timeout};
+use embassy_sync::{blocking_mutex::raw::NoopRawMutex, channel, watch};
+use embassy_time::{Duration, Instant, Timer, with_timeout};
+use serde::{Deserialize, Serialize};
+
+pub type DisplayPowerChannel<const N: usize> =
+    &'static channel::Channel<NoopRawMutex, PowerRequest, N>;
+pub type DisplayPowerDynSender = channel::DynamicSender<'static, PowerRequest>;
+pub type DisplayPowerDynReceiver = channel::DynamicReceiver<'static, PowerRequest>;
+
+/// Requests to power the display on or off, from sources other than the case button.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PowerRequest {
+    On,
+    Off,
+    Toggle,
+    // Off, then on again after the board has settled. Cuts DC power in between,
+    // to recover a hung display controller.
+    Cycle,
+    // Clears a relay latched open by a protection, once the display has cooled.
+    // Leaves the display off.
+    ResetLatch,
+    // Turns the display off if it is on, then resets the controller. Only sent by
+    // the sys/set command, never decoded from a power request.
+    #[serde(skip_deserializing)]
+    Shutdown,
+}
+
+/// Delays in the power sequences that depend on the controller board.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SequenceTimings {
+    /// After DC power comes on, before pressing the power button.
+    pub board_off_dwell_ms: u32,
+    /// After the board turns off, before cutting DC power.
+    pub relay_cut_delay_ms: u32,
+}
+
+impl SequenceTimings {
+    fn board_off_dwell(&self) -> Duration {
+        Duration::from_millis(self.board_off_dwell_ms.min(MAX_SEQUENCE_DELAY_MS) as u64)
+    }
+
+    fn relay_cut_delay(&self) -> Duration {
+        Duration::from_millis(self.relay_cut_delay_ms.min(MAX_SEQUENCE_DELAY_MS) as u64)
+    }
+}
+
+pub type SequenceTimingsWatch<const W: usize> =
+    &'static watch::Watch<NoopRawMutex, SequenceTimings, W>;
+pub type SequenceTimingsDynSender = watch::DynSender<'static, SequenceTimings>;
+pub type SequenceTimingsDynReceiver = watch::DynReceiver<'static, SequenceTimings>;
+
+/// What to do with the display when the controller boots.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PowerRestorePolicy {
+    /// Leave the display off until asked to power on.
+    StayOff,
+    /// Power the display on.
+    AlwaysOn,
+    /// Power the display on if it was last commanded on.
+    RestoreLast,
+}
 
-const BOARD_OFF_DWELL_BEFORE_POWER_BUTTON: Duration = Duration::from_secs(5);
+// The relay always starts open, so any boot (including the one after a
+// blackout) leaves the display dark unless the stored policy says otherwise.
+const DEFAULT_POWER_RESTORE_POLICY: PowerRestorePolicy = PowerRestorePolicy::StayOff;
+
+// Measured on an R1811 controller board. Other revisions may settle at different speeds.
+const DEFAULT_SEQUENCE_TIMINGS: SequenceTimings = SequenceTimings {
+    board_off_dwell_ms: 5000,
+    relay_cut_delay_ms: 5000,
+};
+// Upper bound on each delay, so a bad setting can't push a sequence past its timeout.
+const MAX_SEQUENCE_DELAY_MS: u32 = 8000;
 const POWER_ON_WAIT_TIMEOUT: Duration = Duration::from_secs(2);
 const POWER_OFF_WAIT_BOARD_OFF_TIMEOUT: Duration = Duration::from_secs(2);
-const POWER_OFF_RELAY_CUT_DELAY: Duration = Duration::from_secs(5);
-
-const DISPLAY_POWER_TIMEOUT_PATTERN: BuzzerPattern = &[
-    BuzzerAction::Beep { ms: 320 },
-    BuzzerAction::Pause { ms: 100 },
-    BuzzerAction::Beep { ms: 100 },
-    BuzzerAction::Pause { ms: 100 },
-    BuzzerAction::Beep { ms: 100 },
-    BuzzerAction::Pause { ms: 100 },
-    BuzzerAction::Beep { ms: 100 },
-];
+// How long to leave DC power off during a power cycle, for the board to fully discharge.
+const POWER_CYCLE_SETTLE: Duration = Duration::from_secs(10);
+// Least power draw that confirms the board turned on, as LEDs can light on a
+// controller that then fails to start. Set only with power_monitor's
+// CURRENT_SENSOR_FITTED, or every power-on fails to confirm. Unset, the LEDs
+// alone confirm a power-on.
+const POWER_ON_MIN_DRAW_MW: Option<u32> = None;
+const POWER_ON_CONFIRM_TIMEOUT: Duration = Duration::from_secs(5);
+// How many times to re-press the power button if the board ignored a press.
+const POWER_BUTTON_RETRIES: u8 = 1;
+// Upper bound on a whole power sequence, in case one of its steps never completes.
+// The longest sequence (power-on from DC power off, with a retry and the power draw
+// check) takes about 22s.
+const POWER_SEQUENCE_TIMEOUT: Duration = Duration::from_secs(30);
+// A power cycle runs a power-off and a power-on sequence back to back.
+const POWER_CYCLE_TIMEOUT: Duration = Duration::from_secs(60);
+// How long to wait for pin_control to acknowledge a button press.
+// Covers a queued press ahead of ours plus the press itself.
+const BUTTON_ACK_TIMEOUT: Duration = Duration::from_secs(3);
 
 #[derive(Debug, Copy, Clone, PartialEq)]
 enum SequenceResult {
     Finished,
     TimedOut(&'static str),
     UnexpectedState(DisplayState),
+    PressFailed(PressError),
+    // The LEDs showed the board on, but its power draw never did.
+    Unconfirmed,
+}
+
+// Set while a power sequence runs.
+static SEQUENCE_IN_PROGRESS: AtomicBool = AtomicBool::new(false);
+
+/// Whether a power sequence is running. Other interfaces must not touch the
+/// relay or the power button meanwhile, or they can cut power mid-sequence.
+pub fn sequence_in_progress() -> bool {
+    SEQUENCE_IN_PROGRESS.load(Ordering::Relaxed)
+}
+
+#[must_use]
+pub fn init<const BACKLOG: usize>(
+    settings: Settings,
+) -> (DisplayPowerChannel<BACKLOG>, SequenceTimingsWatch<1>) {
+    let displaypower_channel = Box::leak(Box::new(channel::Channel::new()));
+    let timings = settings
+        .load(settings::SEQUENCE_TIMINGS)
+        .unwrap_or(DEFAULT_SEQUENCE_TIMINGS);
+    let timings_watch = Box::leak(Box::new(watch::Watch::new_with(timings)));
+
+    // Queue the power-restore request, to be handled once display_control starts.
+    let policy = settings
+        .load(settings::POWER_RESTORE_POLICY)
+        .unwrap_or(DEFAULT_POWER_RESTORE_POLICY);
+    let restore_on = match policy {
+        PowerRestorePolicy::StayOff => false,
+        PowerRestorePolicy::AlwaysOn => true,
+        PowerRestorePolicy::RestoreLast => {
+            settings.load(settings::LAST_POWER_STATE).unwrap_or(false)
+        }
+    };
+    if restore_on {
+        audit::commanded(Source::PowerRestore);
+        let _ = displaypower_channel.try_send(PowerRequest::On);
+    }
+
+    (displaypower_channel, timings_watch)
 }
 
 #[embassy_executor::task]
 pub async fn display_control(
     mut casebutton_receiver: CaseButtonDynReceiver,
+    displaypower_receiver: DisplayPowerDynReceiver,
+    mut timings_receiver: SequenceTimingsDynReceiver,
     mut displayboard_receiver: DisplayStateDynReceiver,
-    mut pincontrol_publisher: PinControlPublisher,
-    mut powerrelay_sender: PowerRelayDynSender,
+    mut powerdraw_receiver: PowerDrawDynReceiver,
+    pincontrol_publisher: PinControlPublisher,
+    mut buttonack_subscriber: ButtonAckSubscriber,
+    powerrelay_sender: PowerRelayDynSender,
     buzzer_channel: BuzzerChannel,
+    settings: Settings,
     memlog: SharedLogger,
 ) {
-    loop {
-        // Wait for the case button to be pressed.
-        let button_press = casebutton_receiver.changed().await;
+    // A case button press made during a power sequence, to run once it completes.
+    let mut pending_toggle = false;
+    // A shutdown requested during a power sequence, to run once it completes.
+    let mut pending_shutdown = false;
 
-        // A long press always forces the relay open.
-        if button_press == CaseButton::LongPress {
-            powerrelay_sender.send(RelayCommand::Open).await;
-        }
+    loop {
+        // Wait for the case button to be pressed, or for a power request.
+        let request = if core::mem::take(&mut pending_shutdown) {
+            Some(PowerRequest::Shutdown)
+        } else if core::mem::take(&mut pending_toggle) {
+            memlog.info("dspl_ctl: running button press queued during power sequence");
+            buzzer_channel.send(NamedPattern::Ack.into()).await;
+            Some(PowerRequest::Toggle)
+        } else {
+            match select(
+                casebutton_receiver.changed(),
+                displaypower_receiver.receive(),
+            )
+            .await
+            {
+                // A long press always forces the relay open.
+                Either::First(CaseButton::LongPress) => {
+                    audit::commanded(Source::CaseButton);
+                    powerrelay_sender.send(RelayCommand::Open).await;
+                    remember_power_state(settings, false, memlog);
+                    None
+                }
+                // Held on past the long press: bring the display back through a
+                // power cycle, to recover a hung controller board.
+                Either::First(CaseButton::VeryLongPress) => {
+                    audit::commanded(Source::CaseButton);
+                    Some(PowerRequest::Cycle)
+                }
+                Either::First(CaseButton::ShortPress) => {
+                    audit::commanded(Source::CaseButton);
+                    Some(PowerRequest::Toggle)
+                }
+                Either::Second(request) => Some(request),
+            }
+        };
 
-        // For a short press, find our current state, and dispatch a
-        // corresponding power-on or power-off sequence.
-        if button_press == CaseButton::ShortPress {
+        // Find our current state, and dispatch a corresponding power-on or
+        // power-off sequence.
+        if let Some(request) = request {
             let display_state = displayboard_receiver.get().await;
 
+            if request == PowerRequest::ResetLatch {
+                if display_state != DisplayState::RelayLatchedFault {
+                    memlog.info("dspl_ctl: relay not latched, nothing to reset");
+                } else if let Err(rejected) = commands::check_latch_reset() {
+                    memlog.warn(format!(
+                        "dspl_ctl: relay latch reset refused, {}",
+                        rejected.as_str()
+                    ));
+                } else {
+                    powerrelay_sender.send(RelayCommand::ResetLatch).await;
+                    memlog.warn("dspl_ctl: relay latch reset");
+                }
+                continue;
+            }
+
+            let mut sequencer = Sequencer {
+                timings: timings_receiver
+                    .try_get()
+                    .unwrap_or(DEFAULT_SEQUENCE_TIMINGS),
+                displayboard_receiver: &mut displayboard_receiver,
+                powerdraw_receiver: &mut powerdraw_receiver,
+                pincontrol_publisher: &pincontrol_publisher,
+                buttonack_subscriber: &mut buttonack_subscriber,
+                powerrelay_sender: &powerrelay_sender,
+                memlog,
+            };
+
             use DisplayState::*;
+            use PowerRequest::*;
+            let powering_on = matches!(display_state, DcPowerOff | BoardOff) || request == Cycle;
             let mut power_seq_fut: Pin<Box<dyn Future<Output = SequenceResult>>>;
-            power_seq_fut = match display_state {
-                DcPowerOff => {
-                    let fut = power_on_from_dc_power_off(
-                        &mut displayboard_receiver,
-                        &mut pincontrol_publisher,
-                        &mut powerrelay_sender,
-                    );
-                    Box::pin(fut)
+            power_seq_fut = match (display_state, request) {
+                (DcPowerOff, On | Toggle) => Box::pin(sequencer.power_on_from_dc_power_off()),
+                (BoardOff, On | Toggle) => Box::pin(sequencer.power_on_from_board_off()),
+                (Active | Standby, Off | Toggle | Shutdown) => {
+                    Box::pin(sequencer.power_off_from_operational())
                 }
-
-                BoardOff => {
-                    let fut = power_on_from_board_off(
-                        &mut displayboard_receiver,
-                        &mut pincontrol_publisher,
-                    );
-                    Box::pin(fut)
+                (DcPowerOff | BoardOff | Active | Standby, Cycle) => {
+                    Box::pin(sequencer.power_cycle())
                 }
 
-                Active | Standby => {
-                    let fut = power_off_from_operational(
-                        &mut displayboard_receiver,
-                        &mut pincontrol_publisher,
-                        &mut powerrelay_sender,
-                    );
-                    Box::pin(fut)
-                }
+                // Already in the requested state.
+                (DcPowerOff | BoardOff, Off) | (Active | Standby, On) => continue,
+
+                // Nothing to power off first.
+                (_, Shutdown) => reset_controller(memlog),
 
                 // Can't transition out of these states.
-                Unknown | RelayLatchedFault => continue,
+                (Unknown | RelayLatchedFault, _) => continue,
+
+                // Handled above.
+                (_, ResetLatch) => continue,
             };
+            if request != Shutdown {
+                remember_power_state(settings, powering_on, memlog);
+            }
+
+            METRICS.power_sequence_started();
+            let sequence_started_at = Instant::now();
+            SEQUENCE_IN_PROGRESS.store(true, Ordering::Relaxed);
 
             // Now that we have a future that will perform the sequence of
             // commands, await it while watching for a LongPress. If we get a
@@ -97,116 +293,303 @@ pub async fn display_control(
             let long_press_fut =
                 casebutton_receiver.changed_and(|&press| press == CaseButton::LongPress);
 
-            match select(long_press_fut, &mut power_seq_fut).await {
+            let mut interrupted = false;
+            let guarded_fut = select(long_press_fut, &mut power_seq_fut);
+            let timeout = match request {
+                Cycle => POWER_CYCLE_TIMEOUT,
+                _ => POWER_SEQUENCE_TIMEOUT,
+            };
+            match with_timeout(timeout, guarded_fut).await {
+                // The sequence is stuck. Latch the relay open, which leaves the board in
+                // a fault state until the latch is reset.
+                Err(_timeout) => {
+                    drop(power_seq_fut);
+                    audit::commanded(Source::Protection);
+                    powerrelay_sender.send(RelayCommand::ForceOpenLatch).await;
+                    METRICS.power_sequence_failed();
+                    buzzer_channel.send(NamedPattern::Alarm.into()).await;
+                    memlog.error("dspl_ctl: power sequence stuck, relay latched open");
+                    interrupted = true;
+                }
+
                 // Long press arrived interrupting a sequence.
-                Either::First(_longpress) => {
+                Ok(Either::First(_longpress)) => {
                     drop(power_seq_fut); // terminates the sequence (async cancellation)
+                    audit::commanded(Source::CaseButton);
                     powerrelay_sender.send(RelayCommand::Open).await;
                     memlog.warn("dspl_ctl: long press during power sequence, forced relay off");
+                    interrupted = true;
                 }
 
                 // Sequence completed.
-                Either::Second(result) => match result {
-                    SequenceResult::Finished => memlog.info("dspl_ctl: power sequence complete"),
+                Ok(Either::Second(result)) => match result {
+                    SequenceResult::Finished => {
+                        METRICS.power_sequence_finished(sequence_started_at.elapsed());
+                        if powering_on {
+                            buzzer_channel.send(NamedPattern::PowerOn.into()).await;
+                        }
+                        memlog.info("dspl_ctl: power sequence complete");
+                    }
 
                     SequenceResult::TimedOut(reason) => {
-                        buzzer_channel.send(DISPLAY_POWER_TIMEOUT_PATTERN).await;
+                        METRICS.power_sequence_failed();
+                        buzzer_channel.send(NamedPattern::Timeout.into()).await;
                         memlog.warn(format!("dspl_ctl: power sequence timed out: {reason}"));
                     }
 
                     SequenceResult::UnexpectedState(state) => {
-                        memlog.warn("dspl_ctl: moved to unexpected state: {state:?}")
+                        METRICS.power_sequence_failed();
+                        buzzer_channel.send(NamedPattern::Error.into()).await;
+                        memlog.warn(format!("dspl_ctl: moved to unexpected state: {state:?}"));
+                    }
+
+                    SequenceResult::PressFailed(error) => {
+                        METRICS.power_sequence_failed();
+                        buzzer_channel.send(NamedPattern::Error.into()).await;
+                        memlog.warn(format!("dspl_ctl: power button press failed: {error:?}"));
+                    }
+
+                    // Don't leave a board that may be half-started powered.
+                    SequenceResult::Unconfirmed => {
+                        audit::commanded(Source::Protection);
+                        powerrelay_sender.send(RelayCommand::Open).await;
+                        METRICS.power_sequence_failed();
+                        buzzer_channel.send(NamedPattern::Alarm.into()).await;
+                        memlog
+                            .error("dspl_ctl: power-on not confirmed by power draw, relay opened");
                     }
                 },
             }
+
+            SEQUENCE_IN_PROGRESS.store(false, Ordering::Relaxed);
+
+            if request == Shutdown {
+                reset_controller(memlog);
+            }
+
+            // A short press made while the sequence ran means the user wants the
+            // display back the other way, so run it as a toggle once we're done.
+            // Several presses still make a single toggle.
+            if casebutton_receiver.try_changed() == Some(CaseButton::ShortPress) {
+                if interrupted {
+                    memlog.info("dspl_ctl: ignored button press during interrupted sequence");
+                } else {
+                    pending_toggle = true;
+                }
+            }
+            // Requests queued meanwhile are dropped, except a shutdown, which was
+            // already acknowledged to its sender.
+            while let Ok(request) = displaypower_receiver.try_receive() {
+                if request == PowerRequest::Shutdown {
+                    pending_shutdown = true;
+                } else {
+                    memlog.warn(format!(
+                        "dspl_ctl: rejected {request:?}, power sequence in progress"
+                    ));
+                }
+            }
         }
     }
 }
 
-async fn power_on_from_dc_power_off(
-    displayboard_receiver: &mut DisplayStateDynReceiver,
-    pincontrol_publisher: &PinControlPublisher,
-    powerrelay_sender: &PowerRelayDynSender,
-) -> SequenceResult {
-    use DisplayState::*;
-
-    // Close the relay, providing DC power.
-    powerrelay_sender.send(RelayCommand::Close).await;
-
-    // Wait for a move to BoardOff, or Active/Standby.
-    // We might be there already, so don't wait on a change.
-    // Note: `get_and()` waits for the predicate to match, and also resolves
-    // immediately if it's already matching.
-    let timeout = POWER_ON_WAIT_TIMEOUT;
-    let boardoff_fut = displayboard_receiver
-        .get_and(|&state| state == BoardOff || state == Active || state == Standby);
-    match with_timeout(timeout, boardoff_fut).await {
-        Err(_timeout) => return SequenceResult::TimedOut("no move from power off"),
-        Ok(Active) | Ok(Standby) => return SequenceResult::Finished,
-        Ok(BoardOff) => (),
-        _ => unreachable!(),
+// Keeps the commanded display state, for PowerRestorePolicy::RestoreLast.
+fn remember_power_state(settings: Settings, on: bool, memlog: SharedLogger) {
+    if let Err(error) = settings.store(settings::LAST_POWER_STATE, &on) {
+        memlog.warn(format!("dspl_ctl: failed to store power state: {error:?}"));
     }
+}
 
-    // Now give the board time to physically power on.
-    Timer::after(BOARD_OFF_DWELL_BEFORE_POWER_BUTTON).await;
+fn reset_controller(memlog: SharedLogger) -> ! {
+    memlog.warn("dspl_ctl: resetting controller");
+    esp_hal::system::software_reset()
+}
 
-    // At this stage we might be in BoardOff or in Active/Standby.
-    // If the former, press the power button. If the latter, we're done.
-    match displayboard_receiver.get().await {
-        BoardOff => power_on_from_board_off(displayboard_receiver, pincontrol_publisher).await,
+/// Drives the display board through power sequences.
+struct Sequencer<'a> {
+    timings: SequenceTimings,
+    displayboard_receiver: &'a mut DisplayStateDynReceiver,
+    powerdraw_receiver: &'a mut PowerDrawDynReceiver,
+    pincontrol_publisher: &'a PinControlPublisher,
+    buttonack_subscriber: &'a mut ButtonAckSubscriber,
+    powerrelay_sender: &'a PowerRelayDynSender,
+    memlog: SharedLogger,
+}
 
-        Active | Standby => SequenceResult::Finished,
+impl Sequencer<'_> {
+    /// Pushes the board's power button and waits until pin_control has released it.
+    async fn press_power_button(&mut self) -> Result<(), SequenceResult> {
+        let press = ButtonPress::new(PinControlMessage::ButtonPower);
+        let pressed_at = Instant::now();
+        self.pincontrol_publisher.publish(press).await;
 
-        unexpected => SequenceResult::UnexpectedState(unexpected),
+        // Presses from other senders are acknowledged too, so match on ours.
+        let ack_fut = pin_control::ack_for(self.buttonack_subscriber, press.id);
+        match with_timeout(BUTTON_ACK_TIMEOUT, ack_fut).await {
+            Ok(ButtonAck { result: Ok(()), .. }) => {
+                METRICS.power_press_acknowledged(pressed_at.elapsed());
+                Ok(())
+            }
+            Ok(ButtonAck {
+                result: Err(error), ..
+            }) => Err(SequenceResult::PressFailed(error)),
+            Err(_timeout) => Err(SequenceResult::TimedOut("no button press acknowledgement")),
+        }
     }
-}
 
-async fn power_on_from_board_off(
-    displayboard_receiver: &mut DisplayStateDynReceiver,
-    pincontrol_publisher: &PinControlPublisher,
-) -> SequenceResult {
-    use DisplayState::*;
+    /// Waits for the board's power draw to agree with its LEDs that it turned on.
+    async fn confirm_power_draw(&mut self) -> SequenceResult {
+        let Some(min_mw) = POWER_ON_MIN_DRAW_MW else {
+            return SequenceResult::Finished;
+        };
+
+        let drawing_fut = self
+            .powerdraw_receiver
+            .get_and(|draw| draw.power_mw >= min_mw);
+        match with_timeout(POWER_ON_CONFIRM_TIMEOUT, drawing_fut).await {
+            Ok(_draw) => SequenceResult::Finished,
+            Err(_timeout) => SequenceResult::Unconfirmed,
+        }
+    }
+
+    async fn power_on_from_dc_power_off(&mut self) -> SequenceResult {
+        use DisplayState::*;
+
+        // Close the relay, providing DC power.
+        self.powerrelay_sender.send(RelayCommand::Close).await;
+
+        // Wait for a move to BoardOff, or Active/Standby.
+        // We might be there already, so don't wait on a change.
+        // Note: `get_and()` waits for the predicate to match, and also resolves
+        // immediately if it's already matching.
+        let timeout = POWER_ON_WAIT_TIMEOUT;
+        let boardoff_fut = self
+            .displayboard_receiver
+            .get_and(|&state| state == BoardOff || state == Active || state == Standby);
+        match with_timeout(timeout, boardoff_fut).await {
+            Err(_timeout) => return SequenceResult::TimedOut("no move from power off"),
+            Ok(Active) | Ok(Standby) => return self.confirm_power_draw().await,
+            Ok(BoardOff) => (),
+            _ => unreachable!(),
+        }
+
+        // Now give the board time to physically power on.
+        Timer::after(self.timings.board_off_dwell()).await;
+
+        // At this stage we might be in BoardOff or in Active/Standby.
+        // If the former, press the power button. If the latter, we're done.
+        match self.displayboard_receiver.get().await {
+            BoardOff => self.power_on_from_board_off().await,
+
+            Active | Standby => self.confirm_power_draw().await,
+
+            unexpected => SequenceResult::UnexpectedState(unexpected),
+        }
+    }
+
+    async fn power_on_from_board_off(&mut self) -> SequenceResult {
+        use DisplayState::*;
+
+        for attempt in 0..=POWER_BUTTON_RETRIES {
+            // Only re-press the button if the board still reports being off,
+            // otherwise a late transition would be toggled right back.
+            if attempt > 0 {
+                match self.displayboard_receiver.get().await {
+                    BoardOff => self.memlog.warn(format!(
+                        "dspl_ctl: power button ignored, retry {attempt}/{POWER_BUTTON_RETRIES}"
+                    )),
+                    Active | Standby => return self.confirm_power_draw().await,
+                    unexpected => return SequenceResult::UnexpectedState(unexpected),
+                }
+            }
 
-    // Push the board's power button.
-    pincontrol_publisher
-        .publish(PinControlMessage::ButtonPower)
-        .await;
+            // Push the board's power button.
+            if let Err(result) = self.press_power_button().await {
+                return result;
+            }
 
-    // Expect the board to flash either red or green, switching us to an
-    // operational state (Active or Standby).
-    let timeout = POWER_ON_WAIT_TIMEOUT;
-    let operational_fut =
-        displayboard_receiver.get_and(|&state| state == Active || state == Standby);
+            // Expect the board to flash either red or green, switching us to an
+            // operational state (Active or Standby).
+            let timeout = POWER_ON_WAIT_TIMEOUT;
+            let operational_fut = self
+                .displayboard_receiver
+                .get_and(|&state| state == Active || state == Standby);
+
+            if with_timeout(timeout, operational_fut).await.is_ok() {
+                return self.confirm_power_draw().await;
+            }
+        }
 
-    if let Err(_timeout) = with_timeout(timeout, operational_fut).await {
         SequenceResult::TimedOut("no move to operational")
-    } else {
-        SequenceResult::Finished
     }
-}
 
-async fn power_off_from_operational(
-    displayboard_receiver: &mut DisplayStateDynReceiver,
-    pincontrol_publisher: &PinControlPublisher,
-    powerrelay_sender: &PowerRelayDynSender,
-) -> SequenceResult {
-    use DisplayState::*;
-
-    // Push the board's power button.
-    pincontrol_publisher
-        .publish(PinControlMessage::ButtonPower)
-        .await;
-
-    // Expect the state to transition to BoardOff.
-    let timeout = POWER_OFF_WAIT_BOARD_OFF_TIMEOUT;
-    let boardoff_fut = displayboard_receiver.get_and(|&state| state == BoardOff);
-    if let Err(_timeout) = with_timeout(timeout, boardoff_fut).await {
-        return SequenceResult::TimedOut("no move to board off");
+    async fn power_off_from_operational(&mut self) -> SequenceResult {
+        use DisplayState::*;
+
+        let mut reached_board_off = false;
+        for attempt in 0..=POWER_BUTTON_RETRIES {
+            // Only re-press the button if the board still reports being on.
+            if attempt > 0 {
+                match self.displayboard_receiver.get().await {
+                    Active | Standby => self.memlog.warn(format!(
+                        "dspl_ctl: power button ignored, retry {attempt}/{POWER_BUTTON_RETRIES}"
+                    )),
+                    BoardOff => {
+                        reached_board_off = true;
+                        break;
+                    }
+                    unexpected => return SequenceResult::UnexpectedState(unexpected),
+                }
+            }
+
+            // Push the board's power button.
+            if let Err(result) = self.press_power_button().await {
+                return result;
+            }
+
+            // Expect the state to transition to BoardOff.
+            let timeout = POWER_OFF_WAIT_BOARD_OFF_TIMEOUT;
+            let boardoff_fut = self
+                .displayboard_receiver
+                .get_and(|&state| state == BoardOff);
+            if with_timeout(timeout, boardoff_fut).await.is_ok() {
+                reached_board_off = true;
+                break;
+            }
+        }
+
+        if !reached_board_off {
+            return SequenceResult::TimedOut("no move to board off");
+        }
+
+        // Pause, then open the relay.
+        Timer::after(self.timings.relay_cut_delay()).await;
+        self.powerrelay_sender.send(RelayCommand::Open).await;
+
+        SequenceResult::Finished
     }
 
-    // Pause, then open the relay.
-    Timer::after(POWER_OFF_RELAY_CUT_DELAY).await;
-    powerrelay_sender.send(RelayCommand::Open).await;
+    async fn power_cycle(&mut self) -> SequenceResult {
+        use DisplayState::*;
+
+        match self.displayboard_receiver.get().await {
+            Active | Standby => match self.power_off_from_operational().await {
+                SequenceResult::Finished => (),
+                // A hung board may ignore its power button, so cut its power regardless.
+                SequenceResult::TimedOut(reason) => {
+                    self.memlog
+                        .warn(format!("dspl_ctl: power cycle: {reason}, cutting power"));
+                    self.powerrelay_sender.send(RelayCommand::Open).await;
+                }
+                failed => return failed,
+            },
+            BoardOff => self.powerrelay_sender.send(RelayCommand::Open).await,
+            _ => (),
+        }
+
+        self.memlog
+            .info("dspl_ctl: power cycle: power off, settling");
+        Timer::after(POWER_CYCLE_SETTLE).await;
 
-    SequenceResult::Finished
+        self.power_on_from_dc_power_off().await
+    }
 }
@@ -1,4 +1,5 @@
 use crate::{
+    audit,
     memlog::SharedLogger,
     task::{
         pin_control::{DisplayLedDynReceiver, LedState},
@@ -8,8 +9,9 @@ use crate::{
 use alloc::{boxed::Box, format};
 use embassy_futures::select::{Either, select};
 use embassy_sync::{blocking_mutex::raw::NoopRawMutex, watch};
+use serde::Serialize;
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
 pub enum DisplayState {
     Unknown,
     DcPowerOff,
@@ -83,6 +85,7 @@ pub async fn display_board(
 
                 if display_state != new_state {
                     memlog.info(format!("dspl: {display_state:?} -> {new_state:?}"));
+                    audit::transition(display_state, new_state);
                     display_state = new_state;
                     displayboard_sender.send(display_state);
                 }
@@ -94,6 +97,7 @@ pub async fn display_board(
 
                 if display_state != new_state {
                     memlog.info(format!("dspl: {display_state:?} -> {new_state:?}"));
+                    audit::transition(display_state, new_state);
                     display_state = new_state;
                     displayboard_sender.send(display_state);
                 }
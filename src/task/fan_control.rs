@@ -1,12 +1,21 @@
 use super::temp_sensor::TempSensorDynReceiver;
-use crate::task::fan_control::fan_pid::FanPidController;
+use crate::{
+    memlog::SharedLogger,
+    task::{
+        buzzer::{BuzzerChannel, NamedPattern},
+        display_state::{DisplayState, DisplayStateDynReceiver},
+        fan_control::fan_pid::FanPidController,
+        supervisor::{self, Heartbeat},
+    },
+};
 use alloc::boxed::Box;
+use core::sync::atomic::{AtomicBool, Ordering};
+use embassy_futures::select::{Either, select};
 use embassy_sync::{blocking_mutex::raw::NoopRawMutex, watch};
-use embassy_time::{Duration, Instant, Timer, with_timeout};
+use embassy_time::{Duration, Instant, Timer, with_deadline, with_timeout};
 use esp_hal::{
     gpio,
     ledc::{self, LowSpeed, channel::ChannelIFace, timer::TimerIFace},
-    peripherals::LEDC,
     time,
 };
 
@@ -15,6 +24,24 @@ pub type FanDutyWatch<const W: usize> = &'static watch::Watch<NoopRawMutex, u8,
 pub type FanDutyDynSender = watch::DynSender<'static, u8>;
 pub type FanDutyDynReceiver = watch::DynReceiver<'static, u8>;
 
+/// After the display loses power, keep the fan at full duty until the panel cools below this.
+const FAN_COOLDOWN_TARGET_TEMP_C: f32 = 45.0;
+/// Stop the cool-down after this long regardless, e.g. if the sensor stops reporting.
+const FAN_COOLDOWN_MAX_DURATION: Duration = Duration::from_secs(30 * 60);
+
+/// Without a valid temperature reading for this long, run the fan at full duty.
+const FAN_FAILSAFE_TIMEOUT: Duration = Duration::from_secs(30);
+
+static FAILSAFE_ACTIVE: AtomicBool = AtomicBool::new(false);
+
+/// Whether the fan is being held at full duty for lack of temperature readings.
+pub fn failsafe_active() -> bool {
+    FAILSAFE_ACTIVE.load(Ordering::Relaxed)
+}
+
+/// How long to run the fan at full duty when starting it from a stop.
+const FAN_KICKSTART_DURATION: Duration = Duration::from_secs(2);
+
 /// How often to measure the fan's tachometer.
 pub(crate) const FAN_TACHY_MEASURE_INTERVAL: Duration = Duration::from_secs(10);
 
@@ -25,17 +52,13 @@ pub type FanTachyDynReceiver = watch::DynReceiver<'static, u16>;
 /// Initializes the fan PWM controller to be passed to the fan_duty task.
 #[must_use]
 pub fn init<const WATCHERS: usize>(
-    ledc_peripheral: LEDC<'static>,
+    ledc: &'static ledc::Ledc<'static>,
     pin_fan_pwm: gpio::Output<'static>,
 ) -> (
     ledc::channel::Channel<'static, LowSpeed>,
     FanDutyWatch<WATCHERS>,
     FanTachyWatch<WATCHERS>,
 ) {
-    // LED Controller (LEDC) PWM setup.
-    let mut ledc = ledc::Ledc::new(ledc_peripheral);
-    ledc.set_global_slow_clock(ledc::LSGlobalClkSource::APBClk);
-
     // The timer needs to be 'static for the LEDC channel to also be 'static.
     let mut lstimer0 = ledc.timer::<ledc::LowSpeed>(ledc::timer::Number::Timer0);
     lstimer0
@@ -67,10 +90,25 @@ pub async fn fan_duty(
     pwm_channel: ledc::channel::Channel<'static, LowSpeed>,
     mut fanduty_receiver: FanDutyDynReceiver,
 ) {
+    let mut fan_duty = INITIAL_FAN_DUTY;
+
     loop {
         // Wait for a new duty cycle to be signalled.
-        let new_fan_duty = fanduty_receiver.changed().await;
+        let mut new_fan_duty = fanduty_receiver.changed().await;
+
+        // Low duties may not get a stopped fan turning, so start it at full duty first.
+        if fan_duty == 0 && new_fan_duty > 0 {
+            pwm_channel.set_duty(100).unwrap();
+            Timer::after(FAN_KICKSTART_DURATION).await;
+
+            // Pick up any duty sent during the kick-start.
+            if let Some(latest_fan_duty) = fanduty_receiver.try_changed() {
+                new_fan_duty = latest_fan_duty;
+            }
+        }
+
         pwm_channel.set_duty(new_fan_duty).unwrap(); // Does not fail if timer and channel are configured, and duty ∈ [0,100]
+        fan_duty = new_fan_duty;
     }
 }
 
@@ -100,6 +138,7 @@ pub async fn fan_tachy(
     const GLITCH_DEADTIME_US: u32 = 1_500;
 
     'tachy: loop {
+        supervisor::check_in(Heartbeat::FanTachy);
         Timer::after(FAN_TACHY_MEASURE_INTERVAL).await;
 
         // Synchronize on a first falling edge. If none appears in time, fan is likely stopped.
@@ -161,23 +200,95 @@ pub async fn fan_tachy(
     }
 }
 
-/// Sets the fan duty based on the sensed temperature.
+/// Sets the fan duty based on the sensed temperature while the display has power.
+/// Once it loses power, runs the fan at full speed until the panel has cooled, then
+/// stops it and beeps.
 #[embassy_executor::task]
 pub async fn fan_temp_control(
     fanduty_sender: FanDutyDynSender,
     mut tempsensor_receiver: TempSensorDynReceiver,
+    mut displayboard_receiver: DisplayStateDynReceiver,
+    buzzer_channel: BuzzerChannel,
+    memlog: SharedLogger,
 ) {
     let mut pid_controller = FanPidController::new();
+    // Only beep after cooling down from an actual shutdown, not after boot.
+    let mut display_was_on = false;
+    let mut sensor_deadline = Instant::now() + FAN_FAILSAFE_TIMEOUT;
 
     loop {
-        if let Ok(sensor_temp) = tempsensor_receiver.changed().await.temperature {
-            let new_duty_cycle = pid_controller.update(sensor_temp);
-            fanduty_sender.send(new_duty_cycle as u8);
+        // A latched relay fault also cuts power, but keeps the fan on temperature control.
+        let power_off_fut =
+            displayboard_receiver.changed_and(|&state| state == DisplayState::DcPowerOff);
+        let sensor_fut = with_deadline(sensor_deadline, tempsensor_receiver.changed());
+
+        match select(sensor_fut, power_off_fut).await {
+            Either::First(Ok(reading)) => {
+                if let Ok(sensor_temp) = reading.temperature {
+                    sensor_deadline = reading.timestamp + FAN_FAILSAFE_TIMEOUT;
+                    if FAILSAFE_ACTIVE.swap(false, Ordering::Relaxed) {
+                        memlog.info("fan: temperature readings resumed, back on pid control");
+                    }
+
+                    let new_duty_cycle = pid_controller.update(sensor_temp);
+                    fanduty_sender.send(new_duty_cycle as u8);
+                }
+            }
+
+            // Don't hold whatever duty the PID last produced while blind.
+            Either::First(Err(_timeout)) => {
+                sensor_deadline = Instant::now() + FAN_FAILSAFE_TIMEOUT;
+                if !FAILSAFE_ACTIVE.swap(true, Ordering::Relaxed) {
+                    fanduty_sender.send(100);
+                    memlog.warn("fan: no valid temperature readings, failsafe at 100%");
+                }
+            }
+
+            Either::Second(_power_off) => {
+                fanduty_sender.send(100);
+
+                let power_on_fut =
+                    displayboard_receiver.changed_and(|&state| state != DisplayState::DcPowerOff);
+                if let Either::First(()) =
+                    select(cool_down(&mut tempsensor_receiver), power_on_fut).await
+                {
+                    fanduty_sender.send(0);
+                    if display_was_on {
+                        buzzer_channel.send(NamedPattern::Ack.into()).await;
+                    }
+                    displayboard_receiver
+                        .changed_and(|&state| state != DisplayState::DcPowerOff)
+                        .await;
+                }
+
+                // Start from a clean controller state when the display powers back on.
+                pid_controller = FanPidController::new();
+                sensor_deadline = Instant::now() + FAN_FAILSAFE_TIMEOUT;
+                fanduty_sender.send(INITIAL_FAN_DUTY);
+                display_was_on = true;
+            }
         }
     }
 }
 
+/// Waits for the panel to cool below the cool-down target, or for the cool-down to time out.
+async fn cool_down(tempsensor_receiver: &mut TempSensorDynReceiver) {
+    let cooled_fut = async {
+        loop {
+            if let Ok(sensor_temp) = tempsensor_receiver.changed().await.temperature
+                && sensor_temp < FAN_COOLDOWN_TARGET_TEMP_C
+            {
+                break;
+            }
+        }
+    };
+
+    let _ = with_timeout(FAN_COOLDOWN_MAX_DURATION, cooled_fut).await;
+}
+
 mod fan_pid {
+    use embassy_time::Instant;
+
     // Default target temperature.
     const SETPOINT_TEMP_C: f32 = 65.0;
 
@@ -201,7 +312,21 @@ mod fan_pid {
     const P_TERM_CONTRIBUTION_LIMIT: f32 = 40.0;
     const I_TERM_CONTRIBUTION_LIMIT: f32 = 40.0;
 
-    pub struct FanPidController(pid::Pid<f32>);
+    // Never run the fan below this duty, as it may stall and have to restart.
+    const MIN_RUNNING_DUTY: f32 = 20.0;
+    // Stop the fan below this temperature, and only restart it above the second.
+    // The gap keeps the fan from cycling when the temperature hovers near the threshold.
+    const FAN_OFF_BELOW_TEMP_C: f32 = 40.0;
+    const FAN_ON_ABOVE_TEMP_C: f32 = 45.0;
+    // Maximum rate of change of the duty cycle, in percent per second.
+    const MAX_DUTY_RAMP_PER_S: f32 = 5.0;
+
+    pub struct FanPidController {
+        pid: pid::Pid<f32>,
+        running: bool,
+        last_duty: f32,
+        last_update: Option<Instant>,
+    }
 
     impl FanPidController {
         /// Initializes the fan PID controller with pre-defined gains and limits.
@@ -213,16 +338,50 @@ mod fan_pid {
                 .i(KI_GAIN, I_TERM_CONTRIBUTION_LIMIT);
             //  .d(KD_PARAM, D_TERM_CONTRIBUTION_LIMIT);
 
-            Self(pid_controller)
+            Self {
+                pid: pid_controller,
+                running: true,
+                last_duty: super::INITIAL_FAN_DUTY as f32,
+                last_update: None,
+            }
         }
 
         /// Takes the current temperature measurement and returns the new fan duty cycle.
         pub fn update(&mut self, current_temp_c: f32) -> f32 {
-            let control_signal = self.0.next_control_output(current_temp_c);
+            let control_signal = self.pid.next_control_output(current_temp_c);
 
             // Apply offset to map to [0.0, 100.0].
             // We trust that `output_limit` will have it clamped.
-            control_signal.output + FAN_DUTY_OFFSET
+            let pid_duty = control_signal.output + FAN_DUTY_OFFSET;
+
+            if self.running && current_temp_c < FAN_OFF_BELOW_TEMP_C {
+                self.running = false;
+            } else if !self.running && current_temp_c > FAN_ON_ABOVE_TEMP_C {
+                self.running = true;
+            }
+
+            let target_duty = match self.running {
+                true => pid_duty.max(MIN_RUNNING_DUTY),
+                false => 0.0,
+            };
+
+            // Limit how fast the duty can move, so the fan doesn't audibly hunt.
+            // Stopping is exempt, as there is no floor to ramp through.
+            let now = Instant::now();
+            let duty = match self.last_update {
+                Some(last_update) if self.running => {
+                    let elapsed_s = (now - last_update).as_millis() as f32 / 1000.0;
+                    let max_step = MAX_DUTY_RAMP_PER_S * elapsed_s;
+                    let ramped =
+                        self.last_duty + (target_duty - self.last_duty).clamp(-max_step, max_step);
+                    ramped.max(MIN_RUNNING_DUTY)
+                }
+                _ => target_duty,
+            };
+
+            self.last_duty = duty;
+            self.last_update = Some(now);
+            duty
         }
     }
 }
@@ -0,0 +1,81 @@
+use crate::{
+    audit::{self, Source},
+    memlog::SharedLogger,
+    task::{
+        buzzer::{BuzzerChannel, NamedPattern},
+        display_control::{DisplayPowerDynSender, PowerRequest},
+        fan_control::{FanDutyDynReceiver, FanTachyDynReceiver},
+    },
+};
+use alloc::{boxed::Box, format};
+use embassy_futures::select::{Either, select};
+use embassy_sync::{blocking_mutex::raw::NoopRawMutex, watch};
+use embassy_time::{Duration, Instant};
+
+// Only expect the fan to spin at or above this duty cycle. Matches fan_control's
+// floor for a running fan, where a stall is most likely.
+const FAN_STALL_MIN_DUTY: u8 = 20;
+// How long the fan must report 0 RPM while commanded on to count as stalled.
+const FAN_STALL_TIMEOUT: Duration = Duration::from_secs(30);
+
+pub type FanStallWatch<const W: usize> = &'static watch::Watch<NoopRawMutex, bool, W>;
+pub type FanStallDynSender = watch::DynSender<'static, bool>;
+pub type FanStallDynReceiver = watch::DynReceiver<'static, bool>;
+
+/// Takes a const that sets the maximum number of watchers.
+pub fn init<const WATCHERS: usize>() -> FanStallWatch<WATCHERS> {
+    Box::leak(Box::new(watch::Watch::new_with(false)))
+}
+
+/// Raises an alarm when the fan is commanded on but its tachometer reports no rotation.
+#[embassy_executor::task]
+pub async fn fan_stall_monitor(
+    mut fanduty_receiver: FanDutyDynReceiver,
+    mut fantachy_receiver: FanTachyDynReceiver,
+    fanstall_sender: FanStallDynSender,
+    displaypower_sender: DisplayPowerDynSender,
+    buzzer_channel: BuzzerChannel,
+    memlog: SharedLogger,
+) {
+    let mut duty = fanduty_receiver.get().await;
+    let mut stopped_since: Option<Instant> = None;
+    let mut stalled = false;
+
+    loop {
+        match select(fanduty_receiver.changed(), fantachy_receiver.changed()).await {
+            Either::First(new_duty) => {
+                duty = new_duty;
+                if duty < FAN_STALL_MIN_DUTY {
+                    stopped_since = None;
+                }
+            }
+
+            Either::Second(0) if duty >= FAN_STALL_MIN_DUTY => {
+                let since = *stopped_since.get_or_insert_with(Instant::now);
+                if !stalled && since.elapsed() >= FAN_STALL_TIMEOUT {
+                    stalled = true;
+                    fanstall_sender.send(true);
+                    memlog.error(format!("fanstall: 0rpm at {duty}% duty"));
+                    buzzer_channel.send(NamedPattern::Alarm.into()).await;
+
+                    // Also power the display off, with the `fan-stall-power-off` feature.
+                    // Not a default, as the safety watchdog already cuts the relay on
+                    // overtemp.
+                    if cfg!(feature = "fan-stall-power-off") {
+                        audit::commanded(Source::Protection);
+                        displaypower_sender.send(PowerRequest::Off).await;
+                    }
+                }
+            }
+
+            Either::Second(rpm) => {
+                stopped_since = None;
+                if stalled && rpm > 0 {
+                    stalled = false;
+                    fanstall_sender.send(false);
+                    memlog.info(format!("fanstall: fan recovered, {rpm}rpm"));
+                }
+            }
+        }
+    }
+}
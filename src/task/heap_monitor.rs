@@ -0,0 +1,45 @@
+use crate::{memlog::SharedLogger, metrics::METRICS};
+use alloc::format;
+use embassy_time::{Duration, Ticker};
+
+// How often to check the free heap.
+const HEAP_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+// Shed load when free heap drops below this.
+const HEAP_LOW_BYTES: usize = 16 * 1024;
+// Restore normal operation once free heap is back above this.
+const HEAP_RECOVERED_BYTES: usize = 32 * 1024;
+// Memlog capacity while shedding load.
+const MEMLOG_REDUCED_CAPACITY: usize = 160;
+
+/// Watches free heap, and sheds non-essential memory users when it runs low,
+/// so that a power sequence never hits an allocation failure.
+///
+/// In order: stops streaming log records (each is cloned per watcher), then
+/// shrinks the memlog.
+#[embassy_executor::task]
+pub async fn heap_monitor(memlog: SharedLogger) {
+    let mut ticker = Ticker::every(HEAP_CHECK_INTERVAL);
+    // The capacity to restore, as it was when shedding started. It can be changed
+    // over MQTT at runtime.
+    let mut full_capacity = memlog.capacity();
+    let mut shedding = false;
+
+    loop {
+        ticker.next().await;
+        let free = esp_alloc::HEAP.free();
+        METRICS.heap_free_sampled(free);
+
+        if !shedding && free < HEAP_LOW_BYTES {
+            shedding = true;
+            memlog.warn(format!("heap: low, {free} bytes free, shedding load"));
+            memlog.pause_watch(true);
+            full_capacity = memlog.capacity();
+            memlog.set_capacity(MEMLOG_REDUCED_CAPACITY);
+        } else if shedding && free > HEAP_RECOVERED_BYTES {
+            shedding = false;
+            memlog.set_capacity(full_capacity);
+            memlog.pause_watch(false);
+            memlog.info(format!("heap: recovered, {free} bytes free"));
+        }
+    }
+}
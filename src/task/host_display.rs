@@ -0,0 +1,55 @@
+use crate::{
+    audit::{self, Source},
+    memlog::SharedLogger,
+    task::display_control::{DisplayPowerDynSender, PowerRequest},
+};
+use alloc::{boxed::Box, format};
+use embassy_sync::{blocking_mutex::raw::NoopRawMutex, watch};
+use embassy_time::{Duration, with_timeout};
+use serde::{Deserialize, Serialize};
+
+/// How long a reported host display state must hold before it is mirrored.
+/// Filters out momentary blanks, e.g. during resolution changes.
+const HOST_DISPLAY_DEBOUNCE: Duration = Duration::from_secs(10);
+
+/// Display sleep state reported by an agent running on the host computer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum HostDisplay {
+    Sleep,
+    Wake,
+}
+
+pub type HostDisplayWatch<const W: usize> = &'static watch::Watch<NoopRawMutex, HostDisplay, W>;
+pub type HostDisplayDynSender = watch::DynSender<'static, HostDisplay>;
+pub type HostDisplayDynReceiver = watch::DynReceiver<'static, HostDisplay>;
+
+pub fn init<const WATCHERS: usize>() -> HostDisplayWatch<WATCHERS> {
+    Box::leak(Box::new(watch::Watch::new()))
+}
+
+/// Mirrors the host's display sleep and wake events onto the display.
+#[embassy_executor::task]
+pub async fn host_display_follow(
+    mut hostdisplay_receiver: HostDisplayDynReceiver,
+    displaypower_sender: DisplayPowerDynSender,
+    memlog: SharedLogger,
+) {
+    loop {
+        let mut host_display = hostdisplay_receiver.changed().await;
+
+        // Wait for the reported state to settle.
+        while let Ok(new_host_display) =
+            with_timeout(HOST_DISPLAY_DEBOUNCE, hostdisplay_receiver.changed()).await
+        {
+            host_display = new_host_display;
+        }
+
+        let request = match host_display {
+            HostDisplay::Sleep => PowerRequest::Off,
+            HostDisplay::Wake => PowerRequest::On,
+        };
+        memlog.info(format!("host: display {host_display:?}, power {request:?}"));
+        audit::commanded(Source::HostDisplay);
+        displaypower_sender.send(request).await;
+    }
+}
@@ -0,0 +1,117 @@
+//! Follows the host computer's presence on the network, for hosts that don't run
+//! a display agent. Drives the same path as host display reports, so the
+//! debounce and audit attribution apply.
+
+use crate::{
+    memlog::SharedLogger,
+    settings::{self, Settings},
+    task::host_display::{HostDisplay, HostDisplayDynSender},
+};
+use alloc::{boxed::Box, format};
+use embassy_futures::select::{Either, select};
+use embassy_net::{
+    Ipv4Address,
+    icmp::{
+        PacketMetadata,
+        ping::{PingManager, PingParams},
+    },
+};
+use embassy_sync::{blocking_mutex::raw::NoopRawMutex, watch};
+use embassy_time::{Duration, Instant, Timer};
+use serde::{Deserialize, Serialize};
+
+// Off until a host is set, as most hosts run a display agent instead.
+const DEFAULT_PRESENCE_CONFIG: PresenceConfig = PresenceConfig { host: None };
+const HOST_PRESENCE_PROBE_INTERVAL: Duration = Duration::from_secs(10);
+const HOST_PRESENCE_PROBE_TIMEOUT: Duration = Duration::from_secs(2);
+// How long the host must stop answering before the display is powered off.
+// Covers reboots and brief network outages.
+const HOST_PRESENCE_GRACE: Duration = Duration::from_secs(5 * 60);
+
+/// Settings for following the host computer's presence.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PresenceConfig {
+    /// IPv4 address of the host to follow. Unset, presence detection is off.
+    pub host: Option<[u8; 4]>,
+}
+
+pub type PresenceConfigWatch<const W: usize> =
+    &'static watch::Watch<NoopRawMutex, PresenceConfig, W>;
+pub type PresenceConfigDynSender = watch::DynSender<'static, PresenceConfig>;
+pub type PresenceConfigDynReceiver = watch::DynReceiver<'static, PresenceConfig>;
+
+pub fn init<const WATCHERS: usize>(settings: Settings) -> PresenceConfigWatch<WATCHERS> {
+    let presence_config = settings
+        .load(settings::PRESENCE_CONFIG)
+        .unwrap_or(DEFAULT_PRESENCE_CONFIG);
+    Box::leak(Box::new(watch::Watch::new_with(presence_config)))
+}
+
+/// Follows the configured host, starting over whenever the settings change.
+#[embassy_executor::task]
+pub async fn host_presence(
+    stack: embassy_net::Stack<'static>,
+    mut presenceconfig_receiver: PresenceConfigDynReceiver,
+    hostdisplay_sender: HostDisplayDynSender,
+    memlog: SharedLogger,
+) {
+    let mut rx_meta = [PacketMetadata::EMPTY; 1];
+    let mut rx_buffer = [0u8; 128];
+    let mut tx_meta = [PacketMetadata::EMPTY; 1];
+    let mut tx_buffer = [0u8; 128];
+    let mut ping_manager = PingManager::new(
+        stack,
+        &mut rx_meta,
+        &mut rx_buffer,
+        &mut tx_meta,
+        &mut tx_buffer,
+    );
+
+    let mut presence_config = presenceconfig_receiver.get().await;
+    loop {
+        let Some(host_addr) = presence_config.host else {
+            presence_config = presenceconfig_receiver.changed().await;
+            continue;
+        };
+        let host_addr = Ipv4Address::from(host_addr);
+        memlog.info(format!("presence: following host {host_addr}"));
+
+        let follow_fut = follow_host(&mut ping_manager, host_addr, &hostdisplay_sender, memlog);
+        match select(follow_fut, presenceconfig_receiver.changed()).await {
+            Either::First(never) => never,
+            Either::Second(new_config) => presence_config = new_config,
+        }
+    }
+}
+
+/// Pings the host periodically, waking the display when it appears and
+/// putting it to sleep once it has been gone for the grace period.
+async fn follow_host(
+    ping_manager: &mut PingManager<'_>,
+    host_addr: Ipv4Address,
+    hostdisplay_sender: &HostDisplayDynSender,
+    memlog: SharedLogger,
+) -> ! {
+    let mut params = PingParams::new(host_addr);
+    params.set_count(1).set_timeout(HOST_PRESENCE_PROBE_TIMEOUT);
+
+    let mut present: Option<bool> = None;
+    let mut last_seen = Instant::now();
+
+    loop {
+        Timer::after(HOST_PRESENCE_PROBE_INTERVAL).await;
+
+        if ping_manager.ping(&params).await.is_ok() {
+            last_seen = Instant::now();
+            if present != Some(true) {
+                present = Some(true);
+                memlog.info(format!("presence: host {host_addr} up"));
+                hostdisplay_sender.send(HostDisplay::Wake);
+            }
+        } else if present != Some(false) && last_seen.elapsed() >= HOST_PRESENCE_GRACE {
+            present = Some(false);
+            memlog.info(format!("presence: host {host_addr} gone"));
+            hostdisplay_sender.send(HostDisplay::Sleep);
+        }
+    }
+}
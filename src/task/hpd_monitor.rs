@@ -0,0 +1,111 @@
+use crate::{
+    audit::{self, Source},
+    memlog::SharedLogger,
+    settings::{self, Settings},
+    task::display_control::{DisplayPowerDynSender, PowerRequest},
+};
+use alloc::boxed::Box;
+use embassy_futures::select::{Either3, select3};
+use embassy_sync::{blocking_mutex::raw::NoopRawMutex, watch};
+use embassy_time::{Duration, Instant, Timer};
+use esp_hal::gpio;
+use serde::{Deserialize, Serialize};
+
+// Off by default, as the sense line is an optional hardware addition.
+const DEFAULT_HPD_CONFIG: HpdConfig = HpdConfig {
+    enabled: false,
+    off_after_s: 5 * 60,
+};
+// Lower bound on the disconnect timeout, so replugging a cable doesn't cycle the display.
+const MIN_HPD_OFF_AFTER_S: u32 = 30;
+// How long the line must hold a new level to count as a change.
+const HPD_DEBOUNCE: Duration = Duration::from_millis(500);
+
+/// Settings for following the video source's hot-plug detect line.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HpdConfig {
+    pub enabled: bool,
+    pub off_after_s: u32,
+}
+
+impl HpdConfig {
+    fn off_after(&self) -> Duration {
+        Duration::from_secs(self.off_after_s.max(MIN_HPD_OFF_AFTER_S) as u64)
+    }
+}
+
+pub type HpdConfigWatch<const W: usize> = &'static watch::Watch<NoopRawMutex, HpdConfig, W>;
+pub type HpdConfigDynSender = watch::DynSender<'static, HpdConfig>;
+pub type HpdConfigDynReceiver = watch::DynReceiver<'static, HpdConfig>;
+
+pub fn init<const WATCHERS: usize>(settings: Settings) -> HpdConfigWatch<WATCHERS> {
+    let hpd_config = settings
+        .load(settings::HPD_CONFIG)
+        .unwrap_or(DEFAULT_HPD_CONFIG);
+    Box::leak(Box::new(watch::Watch::new_with(hpd_config)))
+}
+
+/// Powers the display on when a video source is plugged in, and off once it has
+/// been unplugged for a while.
+#[embassy_executor::task]
+pub async fn hpd_monitor(
+    pin: gpio::AnyPin<'static>,
+    mut hpdconfig_receiver: HpdConfigDynReceiver,
+    displaypower_sender: DisplayPowerDynSender,
+    memlog: SharedLogger,
+) {
+    // The line reads high while a source is connected. Pull down so a missing
+    // sense wire reads as no source.
+    let mut hpd_pin = gpio::Input::new(
+        pin,
+        gpio::InputConfig::default().with_pull(gpio::Pull::Down),
+    );
+
+    let mut hpd_config = hpdconfig_receiver.get().await;
+    let mut connected = hpd_pin.is_high();
+    let mut disconnected_since: Option<Instant> = None;
+
+    loop {
+        let power_off_at = match disconnected_since {
+            Some(since) if hpd_config.enabled => since + hpd_config.off_after(),
+            _ => Instant::MAX,
+        };
+
+        match select3(
+            hpd_pin.wait_for_any_edge(),
+            hpdconfig_receiver.changed(),
+            Timer::at(power_off_at),
+        )
+        .await
+        {
+            Either3::First(()) => {
+                Timer::after(HPD_DEBOUNCE).await;
+                if hpd_pin.is_high() == connected {
+                    continue;
+                }
+                connected = !connected;
+
+                if connected {
+                    memlog.info("hpd: source connected");
+                    disconnected_since = None;
+                    if hpd_config.enabled {
+                        audit::commanded(Source::HotPlug);
+                        displaypower_sender.send(PowerRequest::On).await;
+                    }
+                } else {
+                    memlog.info("hpd: source disconnected");
+                    disconnected_since = Some(Instant::now());
+                }
+            }
+
+            Either3::Second(new_config) => hpd_config = new_config,
+
+            Either3::Third(()) => {
+                memlog.info("hpd: no source for too long, powering off");
+                disconnected_since = None;
+                audit::commanded(Source::HotPlug);
+                displaypower_sender.send(PowerRequest::Off).await;
+            }
+        }
+    }
+}
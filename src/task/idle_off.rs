@@ -0,0 +1,97 @@
+use crate::{
+    audit::{self, Source},
+    memlog::SharedLogger,
+    settings::{self, Settings},
+    task::{
+        display_control::{DisplayPowerDynSender, PowerRequest},
+        display_state::{DisplayState, DisplayStateDynReceiver},
+    },
+};
+use alloc::{boxed::Box, format};
+use embassy_futures::select::{Either3, select3};
+use embassy_sync::{blocking_mutex::raw::NoopRawMutex, watch};
+use embassy_time::{Duration, Instant, Timer};
+use serde::{Deserialize, Serialize};
+
+// Idle power-off is opt-in, as some sources take a while to wake their outputs.
+const DEFAULT_IDLE_CONFIG: IdleConfig = IdleConfig {
+    enabled: false,
+    timeout_s: 30 * 60,
+};
+// Lower bound on the idle timeout, so a bad setting can't cut power right after power-on.
+const MIN_IDLE_TIMEOUT_S: u32 = 60;
+
+/// Settings for powering the display off after it sits in standby (no signal).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IdleConfig {
+    pub enabled: bool,
+    pub timeout_s: u32,
+}
+
+impl IdleConfig {
+    fn timeout(&self) -> Duration {
+        Duration::from_secs(self.timeout_s.max(MIN_IDLE_TIMEOUT_S) as u64)
+    }
+}
+
+pub type IdleConfigWatch<const W: usize> = &'static watch::Watch<NoopRawMutex, IdleConfig, W>;
+pub type IdleConfigDynSender = watch::DynSender<'static, IdleConfig>;
+pub type IdleConfigDynReceiver = watch::DynReceiver<'static, IdleConfig>;
+
+pub fn init<const WATCHERS: usize>(settings: Settings) -> IdleConfigWatch<WATCHERS> {
+    let idle_config = settings
+        .load(settings::IDLE_CONFIG)
+        .unwrap_or(DEFAULT_IDLE_CONFIG);
+    Box::leak(Box::new(watch::Watch::new_with(idle_config)))
+}
+
+/// Powers the display off once it has been without a signal for too long.
+#[embassy_executor::task]
+pub async fn idle_power_off(
+    mut idleconfig_receiver: IdleConfigDynReceiver,
+    mut displayboard_receiver: DisplayStateDynReceiver,
+    displaypower_sender: DisplayPowerDynSender,
+    memlog: SharedLogger,
+) {
+    let mut idle_config = idleconfig_receiver.get().await;
+    let mut standby_since: Option<Instant> = None;
+
+    loop {
+        let power_off_at = match standby_since {
+            Some(since) if idle_config.enabled => since + idle_config.timeout(),
+            _ => Instant::MAX,
+        };
+
+        match select3(
+            idleconfig_receiver.changed(),
+            displayboard_receiver.changed(),
+            Timer::at(power_off_at),
+        )
+        .await
+        {
+            Either3::First(new_idle_config) => {
+                idle_config = new_idle_config;
+                let IdleConfig { enabled, .. } = idle_config;
+                let timeout_s = idle_config.timeout().as_secs();
+                memlog.info(format!("idle: enabled={enabled} timeout={timeout_s}s"));
+            }
+
+            Either3::Second(display_state) => {
+                if display_state == DisplayState::Standby {
+                    standby_since.get_or_insert_with(Instant::now);
+                } else {
+                    standby_since = None;
+                }
+            }
+
+            Either3::Third(()) => {
+                memlog.info("idle: no signal for too long, powering off");
+                audit::commanded(Source::IdleOff);
+                displaypower_sender.send(PowerRequest::Off).await;
+
+                // Restart the count, in case the display stays in standby.
+                standby_since = Some(Instant::now());
+            }
+        }
+    }
+}
@@ -1,27 +1,57 @@
 pub mod buzzer;
 pub mod case_button;
+pub mod chip_temp;
+pub mod digest;
 pub mod display_control;
 pub mod display_state;
 pub mod fan_control;
+pub mod fan_stall;
+pub mod heap_monitor;
+pub mod host_display;
+pub mod host_presence;
+pub mod hpd_monitor;
+pub mod idle_off;
 pub mod mqtt;
 pub mod net;
 pub mod net_monitor;
+pub mod net_ping;
+pub mod osd;
 pub mod pin_control;
+pub mod power_monitor;
 pub mod power_relay;
 pub mod safety;
 pub mod serial_tui;
+pub mod supervisor;
+pub mod syslog;
+pub mod telemetry;
 pub mod temp_sensor;
+pub mod udp_control;
 pub mod wifi;
 
 pub use buzzer::buzzer_control;
 pub use case_button::case_button;
+pub use chip_temp::chip_temp;
+pub use digest::digest;
 pub use display_control::display_control;
 pub use display_state::display_board;
 pub use fan_control::fan_duty;
 pub use fan_control::fan_tachy;
 pub use fan_control::fan_temp_control;
+pub use fan_stall::fan_stall_monitor;
+pub use heap_monitor::heap_monitor;
+pub use host_display::host_display_follow;
+pub use host_presence::host_presence;
+pub use hpd_monitor::hpd_monitor;
+pub use idle_off::idle_power_off;
 pub use net_monitor::net_monitor;
+pub use net_ping::net_ping;
+pub use osd::osd_macro;
 pub use pin_control::pin_control;
+pub use power_monitor::power_monitor;
 pub use power_relay::power_relay;
 pub use safety::watchdog;
+pub use supervisor::supervisor;
+pub use syslog::syslog;
+pub use telemetry::telemetry;
 pub use temp_sensor::temp_sensor;
+pub use udp_control::udp_control;
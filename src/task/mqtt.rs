@@ -1,18 +1,46 @@
 use crate::{
-    memlog::SharedLogger,
+    audit::{self, Source},
+    board::BOARD_NAME,
+    commands::{self, Rejected},
+    memlog::{self, Level, SharedLogger},
+    metrics::METRICS,
+    selftest,
+    settings::{self, Settings},
     task::{
+        buzzer::{BuzzerChannel, BuzzerMuteDynSender, BuzzerPattern, NamedPattern},
+        chip_temp::ChipTempDynReceiver,
+        digest::DigestDynReceiver,
+        display_control::{
+            DisplayPowerDynSender, PowerRequest, PowerRestorePolicy, SequenceTimings,
+            SequenceTimingsDynSender,
+        },
         display_state::DisplayStateDynReceiver,
-        fan_control::{FanDutyDynReceiver, FanTachyDynReceiver},
-        net_monitor::NetStatusDynReceiver,
+        fan_control::{self, FanDutyDynReceiver, FanTachyDynReceiver},
+        fan_stall::FanStallDynReceiver,
+        host_display::{HostDisplay, HostDisplayDynSender},
+        host_presence::{PresenceConfig, PresenceConfigDynSender},
+        hpd_monitor::{HpdConfig, HpdConfigDynSender},
+        idle_off::{IdleConfig, IdleConfigDynSender},
+        net::StaticV4,
+        net_monitor::{NetIntervalDynSender, NetStatusDynReceiver},
+        net_ping::{NetPingDynSender, PingRequest},
+        osd::{OsdDynSender, OsdMacro},
         pin_control::{PinControlMessage, PinControlPublisher, PinControlSubscriber},
-        temp_sensor::TempSensorDynReceiver,
+        power_monitor::PowerDrawDynReceiver,
+        telemetry,
+        temp_sensor::{DSPL_TEMP_SENSOR_ADDRESS, TempSensorDynReceiver},
     },
 };
-use alloc::{format, string::ToString};
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec,
+};
 use const_format::concatcp;
 use embassy_net::{IpEndpoint, dns::DnsQueryType, tcp::TcpSocket};
-use embassy_sync::pubsub::WaitResult;
-use embassy_time::{Duration, Timer};
+use embassy_sync::{blocking_mutex::raw::NoopRawMutex, channel, pubsub::WaitResult, watch};
+use embassy_time::{Duration, Instant, Timer};
+use esp_hal::{clock::Clocks, rtc_cntl::reset_reason, system::Cpu};
 use mountain_mqtt::{
     client::{
         Client, ClientError, ClientNoQueue, ClientReceivedEvent, ConnectionSettings, EventHandler,
@@ -26,8 +54,19 @@ use mountain_mqtt::{
     embedded_io_async::ConnectionEmbedded,
     packets::connect::Will,
 };
+use serde::{Deserialize, Serialize};
 
 const MQTT_PING_INTERVAL: Duration = Duration::from_secs(20);
+const METRICS_PUBLISH_INTERVAL: Duration = Duration::from_secs(60);
+// Only publish temperatures that moved by more than these, in degrees Celsius.
+// Only the MQTT topics are thinned out. temp_sensor still sends every reading, as
+// safety treats a gap in readings as a missing sensor and the fan loop steps on each.
+const TEMP_DEADBAND_C: f32 = 0.25;
+const CHIP_TEMP_DEADBAND_C: f32 = 1.0;
+// Upper bound on the display temperature deadband when set at runtime.
+const MAX_TEMP_DEADBAND_C: f32 = 5.0;
+// Publish a temperature at least this often, even if it did not move.
+const TEMP_HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5 * 60);
 const MQTT_SERVER_ADDR: &str = "broker.abu";
 const MQTT_PORT: u16 = 1883;
 const MQTT_TIMEOUT_MS: u32 = 5000;
@@ -42,8 +81,234 @@ macro_rules! mqtt_topic {
     };
 }
 
-/// Topics to subscribe to when connected.
-const SUBSCRIBE_TOPICS: &[&str] = &[mqtt_topic!("control/set")];
+/// Details of the running firmware image, published on connect.
+#[derive(Serialize)]
+struct FirmwareInfo {
+    name: &'static str,
+    version: &'static str,
+    build: &'static str,
+    profile: &'static str,
+}
+const FIRMWARE_INFO: FirmwareInfo = FirmwareInfo {
+    name: env!("CARGO_PKG_NAME"),
+    version: env!("CARGO_PKG_VERSION"),
+    build: env!("FIRMWARE_BUILD_HASH"),
+    profile: if cfg!(debug_assertions) {
+        "debug"
+    } else {
+        "release"
+    },
+};
+
+/// Identifies this controller and its hardware, published on connect.
+#[derive(Serialize)]
+struct Inventory<'a> {
+    device: &'static str,
+    board: &'static str,
+    mac: &'a str,
+    firmware: &'static FirmwareInfo,
+    temp_sensors: &'a [&'a str],
+}
+
+/// Runtime health of the controller, published periodically.
+#[derive(Serialize)]
+struct SysStats<'a> {
+    uptime_s: u64,
+    heap_free: usize,
+    heap_used: usize,
+    heap_free_low_water: Option<usize>,
+    cpu_mhz: u32,
+    reset_reason: &'a str,
+}
+
+/// Health of the display temperature sensor, published periodically.
+#[derive(Serialize)]
+struct TempStatus {
+    consecutive_failures: u32,
+    total_retries: u32,
+    last_success_age_s: Option<u64>,
+}
+
+/// Requests accepted on the relay topic.
+#[derive(Copy, Clone, Debug, Deserialize)]
+enum RelayRequest {
+    ResetLatch,
+}
+
+/// Commands for the controller itself.
+#[derive(Copy, Clone, Debug, Deserialize)]
+enum SysCommand {
+    Reboot,
+    SelfTest,
+    // Erases stored settings, then reboots. Ignored unless `confirm` is set.
+    FactoryReset { confirm: bool },
+}
+
+/// A command turned away, published on `rejected` so its sender finds out why.
+#[derive(Clone, Copy, Debug, Serialize)]
+struct CommandRejection {
+    topic: &'static str,
+    reason: &'static str,
+}
+
+impl CommandRejection {
+    fn new(topic: &'static str, rejected: Rejected) -> Self {
+        CommandRejection {
+            topic,
+            reason: rejected.as_str(),
+        }
+    }
+}
+
+// Rejections from the handler, waiting to be published after each poll.
+type RejectionChannel = channel::Channel<NoopRawMutex, CommandRejection, 4>;
+// The display temperature deadband, as set over MQTT.
+type TempDeadbandWatch = watch::Watch<NoopRawMutex, f32, 1>;
+
+/// Holds back readings that haven't moved since the last one published,
+/// except for a periodic heartbeat.
+struct Deadband {
+    threshold: f32,
+    last: Option<(f32, Instant)>,
+}
+
+impl Deadband {
+    const fn new(threshold: f32) -> Self {
+        Deadband {
+            threshold,
+            last: None,
+        }
+    }
+
+    /// Whether to publish `value`. Records it as published if so.
+    fn admit(&mut self, value: f32) -> bool {
+        let publish = match self.last {
+            Some((last, at)) => {
+                (value - last).abs() > self.threshold || at.elapsed() >= TEMP_HEARTBEAT_INTERVAL
+            }
+            None => true,
+        };
+        if publish {
+            self.last = Some((value, Instant::now()));
+        }
+        publish
+    }
+}
+
+/// A topic the controller takes commands on.
+#[derive(Serialize)]
+struct CommandTopic {
+    topic: &'static str,
+    help: &'static str,
+}
+
+/// Topics to subscribe to when connected. Also published on connect, so clients
+/// can discover the commands without reading the source.
+const COMMAND_TOPICS: &[CommandTopic] = &[
+    CommandTopic {
+        topic: mqtt_topic!("control/set"),
+        help: "press a button, PinControlMessage",
+    },
+    CommandTopic {
+        topic: mqtt_topic!("relay/set"),
+        help: "reset a latched relay once cooled, \"ResetLatch\"",
+    },
+    CommandTopic {
+        topic: mqtt_topic!("power/set"),
+        help: "run a power sequence, PowerRequest",
+    },
+    CommandTopic {
+        topic: mqtt_topic!("osd/set"),
+        help: "run an OSD macro, OsdMacro",
+    },
+    CommandTopic {
+        topic: mqtt_topic!("host/display"),
+        help: "host display sleep state, HostDisplay",
+    },
+    CommandTopic {
+        topic: mqtt_topic!("idle/set"),
+        help: "idle power-off settings, IdleConfig",
+    },
+    CommandTopic {
+        topic: mqtt_topic!("hpd/set"),
+        help: "hot-plug detect follow settings, HpdConfig",
+    },
+    CommandTopic {
+        topic: mqtt_topic!("presence/set"),
+        help: "host presence follow settings, PresenceConfig",
+    },
+    CommandTopic {
+        topic: mqtt_topic!("power/restore"),
+        help: "display power at boot, kept across reboots, PowerRestorePolicy",
+    },
+    CommandTopic {
+        topic: mqtt_topic!("power/timings"),
+        help: "power sequence delays, SequenceTimings",
+    },
+    CommandTopic {
+        topic: mqtt_topic!("temp/deadband"),
+        help: "least change in display temperature to publish, degrees C",
+    },
+    CommandTopic {
+        topic: mqtt_topic!("buzzer/mute"),
+        help: "mute the buzzer, bool",
+    },
+    CommandTopic {
+        topic: mqtt_topic!("log/print"),
+        help: "print logs to the console, bool",
+    },
+    CommandTopic {
+        topic: mqtt_topic!("log/capacity"),
+        help: "log storage size in characters, kept across reboots",
+    },
+    CommandTopic {
+        topic: mqtt_topic!("buzzer/play"),
+        help: "play a NamedPattern or beep,pause,.. ms",
+    },
+    CommandTopic {
+        topic: mqtt_topic!("log/level"),
+        help: "set a log level filter, LogLevelFilter",
+    },
+    CommandTopic {
+        topic: mqtt_topic!("sys/set"),
+        help: "controller command, SysCommand, never retained",
+    },
+    CommandTopic {
+        topic: mqtt_topic!("net/interval"),
+        help: "network poll interval, seconds",
+    },
+    CommandTopic {
+        topic: mqtt_topic!("net/config/set"),
+        help: "static IPv4 settings, kept across reboots, StaticV4",
+    },
+    CommandTopic {
+        topic: mqtt_topic!("net/ping"),
+        help: "ping an address, results on log, PingRequest",
+    },
+];
+
+/// Upper bound on the size of `COMMAND_TOPICS` as JSON, for the buffer it's
+/// serialized into. Counts every character of the strings as escaped.
+const COMMAND_TOPICS_JSON_LEN: usize = {
+    // `{"topic":"","help":""},` around each entry, in `[]`.
+    const ENTRY_OVERHEAD: usize = 23;
+    let mut len = 2;
+    let mut i = 0;
+    while i < COMMAND_TOPICS.len() {
+        let entry = &COMMAND_TOPICS[i];
+        len += ENTRY_OVERHEAD + 2 * (entry.topic.len() + entry.help.len());
+        i += 1;
+    }
+    len
+};
+
+/// Log level filter settings, e.g. `{"level": "Warn"}` or `{"tag": "wifi", "level": "Info"}`.
+/// Omitting `level` resets the global filter, or removes the override for `tag`.
+#[derive(Deserialize)]
+struct LogLevelFilter<'a> {
+    tag: Option<&'a str>,
+    level: Option<Level>,
+}
 
 //
 // Broker connection.
@@ -117,8 +382,25 @@ pub async fn run(
     mut fantachy_receiver: FanTachyDynReceiver,
     pincontrol_publisher: PinControlPublisher,
     mut pincontrol_subscriber: PinControlSubscriber,
+    displaypower_sender: DisplayPowerDynSender,
+    osd_sender: OsdDynSender,
+    hostdisplay_sender: HostDisplayDynSender,
+    idleconfig_sender: IdleConfigDynSender,
+    hpdconfig_sender: HpdConfigDynSender,
+    presenceconfig_sender: PresenceConfigDynSender,
+    sequencetimings_sender: SequenceTimingsDynSender,
+    buzzermute_sender: BuzzerMuteDynSender,
+    netinterval_sender: NetIntervalDynSender,
+    netping_sender: NetPingDynSender,
+    buzzer_channel: BuzzerChannel,
+    settings: Settings,
+    mut last_crash: Option<String>,
     mut netstatus_receiver: NetStatusDynReceiver,
+    mut fanstall_receiver: FanStallDynReceiver,
     mut tempsensor_receiver: TempSensorDynReceiver,
+    mut chiptemp_receiver: ChipTempDynReceiver,
+    mut powerdraw_receiver: PowerDrawDynReceiver,
+    mut digest_receiver: DigestDynReceiver,
     mut displayboard_receiver: DisplayStateDynReceiver,
     memlog: SharedLogger,
 ) {
@@ -146,6 +428,13 @@ pub async fn run(
     let mut rx_buffer = [0u8; 1024];
     let mut tx_buffer = [0u8; 1024];
     let mut mqtt_buffer = [0u8; 2048];
+    let rejections = RejectionChannel::new();
+    let temp_deadband_watch = TempDeadbandWatch::new_with(
+        settings
+            .load(settings::TEMP_DEADBAND)
+            .unwrap_or(TEMP_DEADBAND_C),
+    );
+    let mut tempdeadband_receiver = temp_deadband_watch.receiver().unwrap();
 
     // We continue this loop if the mqtt client is disconnected or failed to connect.
     'connect: loop {
@@ -163,6 +452,21 @@ pub async fn run(
             let delay = MqttDelay;
             let event_handler = MqttHandler {
                 pincontrol_publisher: &pincontrol_publisher,
+                displaypower_sender,
+                osd_sender,
+                hostdisplay_sender: &hostdisplay_sender,
+                idleconfig_sender: &idleconfig_sender,
+                hpdconfig_sender: &hpdconfig_sender,
+                presenceconfig_sender: &presenceconfig_sender,
+                sequencetimings_sender: &sequencetimings_sender,
+                buzzermute_sender: &buzzermute_sender,
+                netinterval_sender: &netinterval_sender,
+                netping_sender,
+                stack,
+                buzzer_channel,
+                settings,
+                rejections: &rejections,
+                temp_deadband_watch: &temp_deadband_watch,
                 memlog,
             };
             let mut mqtt_client =
@@ -178,6 +482,77 @@ pub async fn run(
                 )
                 .await?;
 
+            // Publish details of the running firmware.
+            let firmware_info = serde_json_core::to_string::<_, 128>(&FIRMWARE_INFO).unwrap();
+            mqtt_client
+                .publish(
+                    mqtt_topic!("firmware"),
+                    firmware_info.as_bytes(),
+                    QualityOfService::Qos1,
+                    true,
+                )
+                .await?;
+
+            // Publish the topics we take commands on.
+            let mut commands = vec![0u8; COMMAND_TOPICS_JSON_LEN];
+            let commands_len = serde_json_core::to_slice(COMMAND_TOPICS, &mut commands).unwrap();
+            mqtt_client
+                .publish(
+                    mqtt_topic!("commands"),
+                    &commands[..commands_len],
+                    QualityOfService::Qos1,
+                    true,
+                )
+                .await?;
+            drop(commands);
+
+            // Publish what hardware this controller runs on.
+            let mac = format!("{}", stack.hardware_address());
+            let display_sensor = format!("{DSPL_TEMP_SENSOR_ADDRESS:016X}");
+            let inventory = Inventory {
+                device: MQTT_CLIENT_ID,
+                board: BOARD_NAME,
+                mac: &mac,
+                firmware: &FIRMWARE_INFO,
+                temp_sensors: &[&display_sensor],
+            };
+            let inventory = serde_json_core::to_string::<_, 256>(&inventory).unwrap();
+            mqtt_client
+                .publish(
+                    mqtt_topic!("inventory"),
+                    inventory.as_bytes(),
+                    QualityOfService::Qos1,
+                    true,
+                )
+                .await?;
+
+            // Publish the network settings in use.
+            if let Some(net_config) = StaticV4::current(stack) {
+                let net_config = serde_json_core::to_string::<_, 128>(&net_config).unwrap();
+                mqtt_client
+                    .publish(
+                        mqtt_topic!("net/config"),
+                        net_config.as_bytes(),
+                        QualityOfService::Qos0,
+                        false,
+                    )
+                    .await?;
+            }
+
+            // Publish the report of a crash on the previous boot, once.
+            // Retained until cleared by publishing an empty retained message.
+            if let Some(report) = last_crash.as_ref() {
+                mqtt_client
+                    .publish(
+                        mqtt_topic!("crash"),
+                        report.as_bytes(),
+                        QualityOfService::Qos1,
+                        true,
+                    )
+                    .await?;
+                last_crash = None;
+            }
+
             Ok(mqtt_client)
         }
         .await;
@@ -194,9 +569,9 @@ pub async fn run(
 
         // Subscribe to topics.
         memlog.info("mqtt: subscribing to topics");
-        for sub_topic in SUBSCRIBE_TOPICS {
+        for command_topic in COMMAND_TOPICS {
             if mqtt_client
-                .subscribe(sub_topic, QualityOfService::Qos1)
+                .subscribe(command_topic.topic, QualityOfService::Qos1)
                 .await
                 .is_err()
             {
@@ -217,6 +592,10 @@ pub async fn run(
         'main: loop {
             let catch: Result<(), ClientError> = async {
                 let mut ping_fut = Timer::after(MQTT_PING_INTERVAL);
+                let mut metrics_fut = Timer::after(METRICS_PUBLISH_INTERVAL);
+                let mut temp_deadband =
+                    Deadband::new(tempdeadband_receiver.try_get().unwrap_or(TEMP_DEADBAND_C));
+                let mut chiptemp_deadband = Deadband::new(CHIP_TEMP_DEADBAND_C);
                 // Poor API design of mountain-mqtt forces us to poll periodically.
                 let mut poll_fut = Timer::after_secs(1);
 
@@ -228,9 +607,12 @@ pub async fn run(
                     let net_fut = netstatus_receiver.changed();
                     let log_fut = logwatch_receiver.changed();
                     let dspl_fut = displayboard_receiver.changed();
+                    let chiptemp_fut = chiptemp_receiver.changed();
+                    let fanstall_fut = fanstall_receiver.changed();
+                    let digest_fut = digest_receiver.changed();
 
-                    embassy_infinite_futures::generate_select!(9);
-                    match select9(
+                    embassy_infinite_futures::generate_select!(13);
+                    match select13(
                         temp_fut,
                         fanduty_fut,
                         fantachy_fut,
@@ -240,12 +622,21 @@ pub async fn run(
                         dspl_fut,
                         &mut ping_fut,
                         &mut poll_fut,
+                        &mut metrics_fut,
+                        chiptemp_fut,
+                        fanstall_fut,
+                        digest_fut,
                     )
                     .await
                     {
                         // Publish temperature sensor readings.
-                        Either9::Future1(sensor_data) => {
-                            if let Ok(temp) = sensor_data.temperature {
+                        Either13::Future1(sensor_data) => {
+                            if let Some(threshold) = tempdeadband_receiver.try_changed() {
+                                temp_deadband.threshold = threshold;
+                            }
+                            if let Ok(temp) = sensor_data.temperature
+                                && temp_deadband.admit(temp)
+                            {
                                 mqtt_client
                                     .publish(
                                         mqtt_topic!("temp"),
@@ -258,7 +649,7 @@ pub async fn run(
                         }
 
                         // Publish fan duty values.
-                        Either9::Future2(duty) => {
+                        Either13::Future2(duty) => {
                             mqtt_client
                                 .publish(
                                     mqtt_topic!("fan/duty"),
@@ -270,7 +661,7 @@ pub async fn run(
                         }
 
                         // Publish fan tachy readings.
-                        Either9::Future3(rpms) => {
+                        Either13::Future3(rpms) => {
                             mqtt_client
                                 .publish(
                                     mqtt_topic!("fan/tachy"),
@@ -282,10 +673,10 @@ pub async fn run(
                         }
 
                         // Publish pincontrol commands.
-                        Either9::Future4(pincontrol) => {
-                            if let WaitResult::Message(command) = pincontrol {
+                        Either13::Future4(pincontrol) => {
+                            if let WaitResult::Message(press) = pincontrol {
                                 let command =
-                                    serde_json_core::to_string::<_, 128>(&command).unwrap();
+                                    serde_json_core::to_string::<_, 128>(&press.message).unwrap();
                                 mqtt_client
                                     .publish(
                                         mqtt_topic!("control"),
@@ -298,7 +689,7 @@ pub async fn run(
                         }
 
                         // Publish network status updates.
-                        Either9::Future5(net) => {
+                        Either13::Future5(net) => {
                             mqtt_client
                                 .publish(
                                     mqtt_topic!("net"),
@@ -310,11 +701,11 @@ pub async fn run(
                         }
 
                         // Publish logs.
-                        Either9::Future6(log) => {
+                        Either13::Future6(log) => {
                             mqtt_client
                                 .publish(
                                     mqtt_topic!("log"),
-                                    format!("{log}").as_bytes(),
+                                    format!("#{} {log}", log.seq).as_bytes(),
                                     QualityOfService::Qos0,
                                     false,
                                 )
@@ -322,7 +713,7 @@ pub async fn run(
                         }
 
                         // Publish changes to the display board state.
-                        Either9::Future7(state) => {
+                        Either13::Future7(state) => {
                             mqtt_client
                                 .publish(
                                     mqtt_topic!("state"),
@@ -331,18 +722,196 @@ pub async fn run(
                                     false,
                                 )
                                 .await?;
+
+                            // Follow each transition with the trail that explains it.
+                            let mut history = [0u8; audit::HISTORY_JSON_LEN];
+                            match serde_json_core::to_slice(
+                                audit::history().as_slice(),
+                                &mut history,
+                            ) {
+                                Ok(history_len) => {
+                                    mqtt_client
+                                        .publish(
+                                            mqtt_topic!("state/history"),
+                                            &history[..history_len],
+                                            QualityOfService::Qos0,
+                                            false,
+                                        )
+                                        .await?;
+                                }
+                                Err(_) => memlog.warn("mqtt: state history too large to publish"),
+                            }
                         }
 
                         // Periodically send a ping to the server.
-                        Either9::Future8(_ping) => {
+                        Either13::Future8(_ping) => {
                             mqtt_client.send_ping().await?;
                             ping_fut = Timer::after(MQTT_PING_INTERVAL);
                         }
 
                         // Periodic poll for MQTT messages.
-                        Either9::Future9(_trigger) => {
+                        Either13::Future9(_trigger) => {
                             mqtt_client.poll(false).await?;
                             poll_fut = Timer::after_secs(1);
+
+                            // Tell senders about the commands the handler turned away.
+                            while let Ok(rejection) = rejections.try_receive() {
+                                let Ok(rejection) =
+                                    serde_json_core::to_string::<_, 192>(&rejection)
+                                else {
+                                    memlog.warn("mqtt: rejection too large to publish");
+                                    continue;
+                                };
+                                mqtt_client
+                                    .publish(
+                                        mqtt_topic!("rejected"),
+                                        rejection.as_bytes(),
+                                        QualityOfService::Qos1,
+                                        false,
+                                    )
+                                    .await?;
+                            }
+                        }
+
+                        // Periodically publish counters and system health.
+                        Either13::Future10(_trigger) => {
+                            let metrics =
+                                serde_json_core::to_string::<_, 640>(&METRICS.snapshot()).unwrap();
+                            mqtt_client
+                                .publish(
+                                    mqtt_topic!("metrics"),
+                                    metrics.as_bytes(),
+                                    QualityOfService::Qos0,
+                                    false,
+                                )
+                                .await?;
+
+                            let log_stats =
+                                serde_json_core::to_string::<_, 192>(&memlog.stats()).unwrap();
+                            mqtt_client
+                                .publish(
+                                    mqtt_topic!("log/stats"),
+                                    log_stats.as_bytes(),
+                                    QualityOfService::Qos0,
+                                    false,
+                                )
+                                .await?;
+
+                            let temp_history =
+                                serde_json_core::to_string::<_, 384>(&telemetry::temp_history())
+                                    .unwrap();
+                            mqtt_client
+                                .publish(
+                                    mqtt_topic!("temp/history"),
+                                    temp_history.as_bytes(),
+                                    QualityOfService::Qos0,
+                                    false,
+                                )
+                                .await?;
+
+                            if let Some(power_draw) = powerdraw_receiver.try_get() {
+                                let power_draw =
+                                    serde_json_core::to_string::<_, 64>(&power_draw).unwrap();
+                                mqtt_client
+                                    .publish(
+                                        mqtt_topic!("power/draw"),
+                                        power_draw.as_bytes(),
+                                        QualityOfService::Qos0,
+                                        false,
+                                    )
+                                    .await?;
+                            }
+
+                            mqtt_client
+                                .publish(
+                                    mqtt_topic!("fan/failsafe"),
+                                    fan_control::failsafe_active().to_string().as_bytes(),
+                                    QualityOfService::Qos0,
+                                    false,
+                                )
+                                .await?;
+
+                            if let Some(reading) = tempsensor_receiver.try_get() {
+                                let health = reading.health;
+                                let temp_status = TempStatus {
+                                    consecutive_failures: health.consecutive_failures,
+                                    total_retries: health.total_retries,
+                                    last_success_age_s: health
+                                        .last_success
+                                        .map(|instant| instant.elapsed().as_secs()),
+                                };
+                                let temp_status =
+                                    serde_json_core::to_string::<_, 128>(&temp_status).unwrap();
+                                mqtt_client
+                                    .publish(
+                                        mqtt_topic!("temp/status"),
+                                        temp_status.as_bytes(),
+                                        QualityOfService::Qos0,
+                                        false,
+                                    )
+                                    .await?;
+                            }
+
+                            let reset_reason = format!("{:?}", reset_reason(Cpu::ProCpu));
+                            let sys_stats = SysStats {
+                                uptime_s: Instant::now().as_secs(),
+                                heap_free: esp_alloc::HEAP.free(),
+                                heap_used: esp_alloc::HEAP.used(),
+                                heap_free_low_water: METRICS.heap_free_low_water(),
+                                cpu_mhz: Clocks::get().cpu_clock.as_mhz(),
+                                reset_reason: &reset_reason,
+                            };
+                            let sys_stats =
+                                serde_json_core::to_string::<_, 256>(&sys_stats).unwrap();
+                            mqtt_client
+                                .publish(
+                                    mqtt_topic!("sys"),
+                                    sys_stats.as_bytes(),
+                                    QualityOfService::Qos0,
+                                    false,
+                                )
+                                .await?;
+
+                            metrics_fut = Timer::after(METRICS_PUBLISH_INTERVAL);
+                        }
+
+                        // Publish SoC die temperature readings.
+                        Either13::Future11(chip_temp) => {
+                            if chiptemp_deadband.admit(chip_temp) {
+                                mqtt_client
+                                    .publish(
+                                        mqtt_topic!("temp/chip"),
+                                        format!("{chip_temp:.1}").as_bytes(),
+                                        QualityOfService::Qos0,
+                                        false,
+                                    )
+                                    .await?;
+                            }
+                        }
+
+                        // Publish fan stall state changes.
+                        Either13::Future12(stalled) => {
+                            mqtt_client
+                                .publish(
+                                    mqtt_topic!("fan/stall"),
+                                    stalled.to_string().as_bytes(),
+                                    QualityOfService::Qos0,
+                                    false,
+                                )
+                                .await?;
+                        }
+
+                        // Publish the periodic activity digest.
+                        Either13::Future13(digest) => {
+                            let digest = serde_json_core::to_string::<_, 256>(&digest).unwrap();
+                            mqtt_client
+                                .publish(
+                                    mqtt_topic!("digest"),
+                                    digest.as_bytes(),
+                                    QualityOfService::Qos1,
+                                    false,
+                                )
+                                .await?;
                         }
                     }
                 } // 'select loop
@@ -366,9 +935,61 @@ pub async fn run(
 
 struct MqttHandler<'h> {
     pincontrol_publisher: &'h PinControlPublisher,
+    displaypower_sender: DisplayPowerDynSender,
+    osd_sender: OsdDynSender,
+    hostdisplay_sender: &'h HostDisplayDynSender,
+    idleconfig_sender: &'h IdleConfigDynSender,
+    hpdconfig_sender: &'h HpdConfigDynSender,
+    presenceconfig_sender: &'h PresenceConfigDynSender,
+    sequencetimings_sender: &'h SequenceTimingsDynSender,
+    buzzermute_sender: &'h BuzzerMuteDynSender,
+    netinterval_sender: &'h NetIntervalDynSender,
+    netping_sender: NetPingDynSender,
+    stack: embassy_net::Stack<'static>,
+    buzzer_channel: BuzzerChannel,
+    settings: Settings,
+    rejections: &'h RejectionChannel,
+    temp_deadband_watch: &'h TempDeadbandWatch,
     memlog: SharedLogger,
 }
 
+impl MqttHandler<'_> {
+    /// Stores a setting that was just applied, so it also holds after a reboot.
+    fn persist<T: Serialize>(&self, key: &str, value: &T) {
+        if let Err(error) = self.settings.store(key, value) {
+            self.memlog.warn(format!(
+                "mqtt: failed to store {key}, kept until reboot: {error:?}"
+            ));
+        }
+    }
+    /// Logs a rejected command, and queues it for publishing on `rejected`.
+    fn reject(&self, topic: &'static str, what: &str, rejected: Rejected) {
+        self.memlog
+            .warn(format!("mqtt: {what} rejected, {}", rejected.as_str()));
+        if self
+            .rejections
+            .try_send(CommandRejection::new(topic, rejected))
+            .is_err()
+        {
+            self.memlog.warn("mqtt: rejection queue full");
+        }
+    }
+
+    /// Has display_control power the display off, then reset the controller.
+    /// Returns whether the reset was scheduled.
+    fn shutdown(&self) -> bool {
+        let scheduled = self
+            .displaypower_sender
+            .try_send(PowerRequest::Shutdown)
+            .is_ok();
+        if !scheduled {
+            self.memlog.warn("mqtt: power request queue full");
+        }
+
+        scheduled
+    }
+}
+
 impl<'h, const P: usize> EventHandler<P> for MqttHandler<'h> {
     async fn handle_event(
         &mut self,
@@ -381,10 +1002,333 @@ impl<'h, const P: usize> EventHandler<P> for MqttHandler<'h> {
         // Receive pincontrol commands on devices/display/<id>/control/set
         if message.topic_name.eq(mqtt_topic!("control/set")) {
             match serde_json_core::from_slice::<PinControlMessage>(message.payload) {
-                Ok((command, _remainder)) => self.pincontrol_publisher.publish(command).await,
+                Ok((command, _remainder)) => {
+                    let sent = commands::check_button(command).and_then(|()| {
+                        audit::commanded(Source::Mqtt);
+                        commands::send_button(self.pincontrol_publisher, command)
+                    });
+                    if let Err(rejected) = sent {
+                        let what = format!("{:?} press", command.button());
+                        self.reject(mqtt_topic!("control/set"), &what, rejected);
+                    }
+                }
+                Err(error) => self
+                    .memlog
+                    .warn(format!("mqtt: failed to deserialize pin command: {error}")),
+            }
+
+            Ok(())
+        } else if message.topic_name.eq(mqtt_topic!("relay/set")) {
+            // Receive relay latch resets on devices/display/<id>/relay/set.
+            // The relay is otherwise only driven through display_control's sequences.
+            match serde_json_core::from_slice::<RelayRequest>(message.payload) {
+                Ok((RelayRequest::ResetLatch, _remainder)) => {
+                    audit::commanded(Source::Mqtt);
+                    if self
+                        .displaypower_sender
+                        .try_send(PowerRequest::ResetLatch)
+                        .is_err()
+                    {
+                        self.memlog.warn("mqtt: power request queue full");
+                    }
+                }
+                Err(error) => self.memlog.warn(format!(
+                    "mqtt: failed to deserialize relay request: {error}"
+                )),
+            }
+
+            Ok(())
+        } else if message.topic_name.eq(mqtt_topic!("power/set")) {
+            // Receive power requests on devices/display/<id>/power/set
+            match serde_json_core::from_slice::<PowerRequest>(message.payload) {
+                Ok((request, _remainder)) => {
+                    let sent = commands::check_power(request).and_then(|()| {
+                        audit::commanded(Source::Mqtt);
+                        commands::send_power(&self.displaypower_sender, request)
+                    });
+                    if let Err(rejected) = sent {
+                        let what = format!("{request:?} request");
+                        self.reject(mqtt_topic!("power/set"), &what, rejected);
+                    }
+                }
+                Err(error) => self.memlog.warn(format!(
+                    "mqtt: failed to deserialize power request: {error}"
+                )),
+            }
+
+            Ok(())
+        } else if message.topic_name.eq(mqtt_topic!("osd/set")) {
+            // Receive OSD macros on devices/display/<id>/osd/set
+            match serde_json_core::from_slice::<OsdMacro>(message.payload) {
+                Ok((osd_macro, _remainder)) => {
+                    if self.osd_sender.try_send(osd_macro).is_err() {
+                        self.memlog.warn("mqtt: osd macro queue full");
+                    }
+                }
+                Err(error) => self
+                    .memlog
+                    .warn(format!("mqtt: failed to deserialize osd macro: {error}")),
+            }
+
+            Ok(())
+        } else if message.topic_name.eq(mqtt_topic!("host/display")) {
+            // Receive host display sleep/wake events on devices/display/<id>/host/display
+            match serde_json_core::from_slice::<HostDisplay>(message.payload) {
+                Ok((host_display, _remainder)) => self.hostdisplay_sender.send(host_display),
+                Err(error) => self.memlog.warn(format!(
+                    "mqtt: failed to deserialize host display state: {error}"
+                )),
+            }
+
+            Ok(())
+        } else if message.topic_name.eq(mqtt_topic!("idle/set")) {
+            // Receive idle power-off settings on devices/display/<id>/idle/set
+            match serde_json_core::from_slice::<IdleConfig>(message.payload) {
+                Ok((idle_config, _remainder)) => {
+                    self.idleconfig_sender.send(idle_config);
+                    self.persist(settings::IDLE_CONFIG, &idle_config);
+                }
+                Err(error) => self.memlog.warn(format!(
+                    "mqtt: failed to deserialize idle settings: {error}"
+                )),
+            }
+
+            Ok(())
+        } else if message.topic_name.eq(mqtt_topic!("hpd/set")) {
+            // Receive hot-plug detect settings on devices/display/<id>/hpd/set
+            match serde_json_core::from_slice::<HpdConfig>(message.payload) {
+                Ok((hpd_config, _remainder)) => {
+                    self.hpdconfig_sender.send(hpd_config);
+                    self.persist(settings::HPD_CONFIG, &hpd_config);
+                }
+                Err(error) => self
+                    .memlog
+                    .warn(format!("mqtt: failed to deserialize hpd settings: {error}")),
+            }
+
+            Ok(())
+        } else if message.topic_name.eq(mqtt_topic!("presence/set")) {
+            // Receive host presence settings on devices/display/<id>/presence/set
+            match serde_json_core::from_slice::<PresenceConfig>(message.payload) {
+                Ok((presence_config, _remainder)) => {
+                    self.presenceconfig_sender.send(presence_config);
+                    self.persist(settings::PRESENCE_CONFIG, &presence_config);
+                }
+                Err(error) => self.memlog.warn(format!(
+                    "mqtt: failed to deserialize presence settings: {error}"
+                )),
+            }
+
+            Ok(())
+        } else if message.topic_name.eq(mqtt_topic!("power/restore")) {
+            // Receive the power-restore policy on devices/display/<id>/power/restore
+            match serde_json_core::from_slice::<PowerRestorePolicy>(message.payload) {
+                Ok((policy, _remainder)) => {
+                    match self.settings.store(settings::POWER_RESTORE_POLICY, &policy) {
+                        Ok(()) => self.memlog.info(format!("mqtt: power restore {policy:?}")),
+                        Err(error) => self
+                            .memlog
+                            .warn(format!("mqtt: failed to store power restore: {error:?}")),
+                    }
+                }
+                Err(error) => self.memlog.warn(format!(
+                    "mqtt: failed to deserialize power restore policy: {error}"
+                )),
+            }
+
+            Ok(())
+        } else if message.topic_name.eq(mqtt_topic!("power/timings")) {
+            // Receive power sequence delays on devices/display/<id>/power/timings
+            match serde_json_core::from_slice::<SequenceTimings>(message.payload) {
+                Ok((timings, _remainder)) => {
+                    self.memlog.info(format!("mqtt: power timings {timings:?}"));
+                    self.sequencetimings_sender.send(timings);
+                    self.persist(settings::SEQUENCE_TIMINGS, &timings);
+                }
+                Err(error) => self.memlog.warn(format!(
+                    "mqtt: failed to deserialize power timings: {error}"
+                )),
+            }
+
+            Ok(())
+        } else if message.topic_name.eq(mqtt_topic!("temp/deadband")) {
+            // Receive the display temperature deadband on devices/display/<id>/temp/deadband
+            match serde_json_core::from_slice::<f32>(message.payload) {
+                Ok((threshold, _remainder)) if (0.0..=MAX_TEMP_DEADBAND_C).contains(&threshold) => {
+                    self.temp_deadband_watch.sender().send(threshold);
+                    self.persist(settings::TEMP_DEADBAND, &threshold);
+                }
+                Ok((threshold, _remainder)) => self.memlog.warn(format!(
+                    "mqtt: temperature deadband {threshold} out of range"
+                )),
+                Err(error) => self.memlog.warn(format!(
+                    "mqtt: failed to deserialize temperature deadband: {error}"
+                )),
+            }
+
+            Ok(())
+        } else if message.topic_name.eq(mqtt_topic!("buzzer/mute")) {
+            // Receive the buzzer mute flag on devices/display/<id>/buzzer/mute
+            match serde_json_core::from_slice::<bool>(message.payload) {
+                Ok((muted, _remainder)) => {
+                    self.memlog.info(format!("mqtt: buzzer muted: {muted}"));
+                    self.buzzermute_sender.send(muted);
+                }
+                Err(error) => self.memlog.warn(format!(
+                    "mqtt: failed to deserialize buzzer mute flag: {error}"
+                )),
+            }
+
+            Ok(())
+        } else if message.topic_name.eq(mqtt_topic!("log/print")) {
+            // Switch printing logs to the debug console on devices/display/<id>/log/print
+            match serde_json_core::from_slice::<bool>(message.payload) {
+                Ok((true, _remainder)) => self.memlog.enable_print(),
+                Ok((false, _remainder)) => self.memlog.disable_print(),
+                Err(error) => self.memlog.warn(format!(
+                    "mqtt: failed to deserialize log print flag: {error}"
+                )),
+            }
+
+            Ok(())
+        } else if message.topic_name.eq(mqtt_topic!("log/capacity")) {
+            // Receive the log storage capacity on devices/display/<id>/log/capacity
+            match serde_json_core::from_slice::<usize>(message.payload) {
+                Ok((capacity, _remainder)) if capacity <= memlog::MAX_CAPACITY => {
+                    self.memlog.set_capacity(capacity);
+                    self.persist(settings::LOG_CAPACITY, &capacity);
+                }
+                Ok((capacity, _remainder)) => self.memlog.warn(format!(
+                    "mqtt: log capacity {capacity} above {}",
+                    memlog::MAX_CAPACITY
+                )),
+                Err(error) => self
+                    .memlog
+                    .warn(format!("mqtt: failed to deserialize log capacity: {error}")),
+            }
+
+            Ok(())
+        } else if message.topic_name.eq(mqtt_topic!("buzzer/play")) {
+            // Play a buzzer pattern on devices/display/<id>/buzzer/play
+            // Takes a pattern name, or alternating beep/pause durations (e.g. 100,50,100).
+            let pattern = match serde_json_core::from_slice::<NamedPattern>(message.payload) {
+                Ok((named, _remainder)) => Some(named.into()),
+                Err(_) => core::str::from_utf8(message.payload)
+                    .ok()
+                    .and_then(BuzzerPattern::parse_durations),
+            };
+
+            match pattern {
+                Some(pattern) => {
+                    if self.buzzer_channel.try_send(pattern).is_err() {
+                        self.memlog.warn("mqtt: buzzer queue full");
+                    }
+                }
+                None => self.memlog.warn("mqtt: failed to parse buzzer pattern"),
+            }
+
+            Ok(())
+        } else if message.topic_name.eq(mqtt_topic!("log/level")) {
+            // Receive log level filters on devices/display/<id>/log/level
+            match serde_json_core::from_slice::<LogLevelFilter>(message.payload) {
+                Ok((filter, _remainder)) => match filter.tag {
+                    Some(tag) => self.memlog.set_tag_level(tag, filter.level),
+                    None => self.memlog.set_level(filter.level.unwrap_or(Level::Trace)),
+                },
+                Err(error) => self.memlog.warn(format!(
+                    "mqtt: failed to deserialize log level filter: {error}"
+                )),
+            }
+
+            Ok(())
+        } else if message.topic_name.eq(mqtt_topic!("net/interval")) {
+            // Receive the net monitor poll interval (s) on devices/display/<id>/net/interval
+            match serde_json_core::from_slice::<u32>(message.payload) {
+                Ok((interval_s, _remainder)) => {
+                    self.netinterval_sender.send(interval_s);
+                    self.persist(settings::NET_INTERVAL, &interval_s);
+                }
+                Err(error) => self
+                    .memlog
+                    .warn(format!("mqtt: failed to deserialize net interval: {error}")),
+            }
+
+            Ok(())
+        } else if message.topic_name.eq(mqtt_topic!("net/config/set")) {
+            // Receive static IPv4 settings on devices/display/<id>/net/config/set
+            // Applying them drops this connection, which reconnects on the new address.
+            match serde_json_core::from_slice::<StaticV4>(message.payload) {
+                Ok((net_config, _remainder)) if net_config.prefix <= 32 => {
+                    self.memlog.warn(format!("mqtt: applying {net_config:?}"));
+                    // Store first, as applying drops the connection.
+                    self.persist(settings::STATIC_V4, &net_config);
+                    net_config.apply(self.stack);
+                }
+                Ok((net_config, _remainder)) => self
+                    .memlog
+                    .warn(format!("mqtt: invalid prefix /{}", net_config.prefix)),
+                Err(error) => self
+                    .memlog
+                    .warn(format!("mqtt: failed to deserialize net config: {error}")),
+            }
+
+            Ok(())
+        } else if message.topic_name.eq(mqtt_topic!("net/ping")) {
+            // Receive ping requests on devices/display/<id>/net/ping
+            match serde_json_core::from_slice::<PingRequest>(message.payload) {
+                Ok((request, _remainder)) => {
+                    if self.netping_sender.try_send(request).is_err() {
+                        self.memlog.warn("mqtt: ping already queued");
+                    }
+                }
+                Err(error) => self
+                    .memlog
+                    .warn(format!("mqtt: failed to deserialize ping request: {error}")),
+            }
+
+            Ok(())
+        } else if message.topic_name.eq(mqtt_topic!("sys/set")) {
+            // Receive controller commands on devices/display/<id>/sys/set
+            // A retained command would be delivered again on every connect, and
+            // reboot the controller in a loop.
+            if message.retain {
+                self.memlog.warn("mqtt: ignored retained sys command");
+                return Ok(());
+            }
+
+            match serde_json_core::from_slice::<SysCommand>(message.payload) {
+                Ok((SysCommand::Reboot, _remainder)) => {
+                    audit::commanded(Source::Mqtt);
+                    if self.shutdown() {
+                        self.memlog.warn("mqtt: reboot scheduled");
+                    }
+                }
+                Ok((SysCommand::FactoryReset { confirm: false }, _remainder)) => {
+                    self.memlog
+                        .warn("mqtt: factory reset not confirmed, ignored");
+                }
+                Ok((SysCommand::FactoryReset { confirm: true }, _remainder)) => {
+                    audit::commanded(Source::Mqtt);
+                    // Only erase once the reboot is scheduled, so the controller never
+                    // keeps running on erased settings. display_control can't reset
+                    // before this handler returns.
+                    if self.shutdown() {
+                        match self.settings.clear() {
+                            Ok(()) => self
+                                .memlog
+                                .warn("mqtt: factory reset, settings erased, reboot scheduled"),
+                            Err(error) => self.memlog.error(format!(
+                                "mqtt: factory reset failed to erase settings: {error:?}"
+                            )),
+                        }
+                    }
+                }
+                Ok((SysCommand::SelfTest, _remainder)) => {
+                    selftest::run(self.memlog);
+                }
                 Err(error) => self
                     .memlog
-                    .warn(format!("failed to deserialize pin command: {error}")),
+                    .warn(format!("mqtt: failed to deserialize sys command: {error}")),
             }
 
             Ok(())
@@ -392,7 +1336,7 @@ impl<'h, const P: usize> EventHandler<P> for MqttHandler<'h> {
             // Unrecognized topics.
             // Note: we deliberately do not error on an unexpected topic.
             self.memlog
-                .warn(format!("unexpected topic: {}", message.topic_name));
+                .warn(format!("mqtt: unexpected topic: {}", message.topic_name));
 
             Ok(())
         }
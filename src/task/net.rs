@@ -1,18 +1,25 @@
+use crate::settings::{self, Settings};
 use alloc::boxed::Box;
 use embassy_net::{self as net};
 use esp_hal::rng::Rng;
 use esp_radio::wifi;
+use serde::{Deserialize, Serialize};
 
 /// Maximum number of sockets to allocate memory for.
 /// - dhcp: 1 socke
 /// - dns:  1 socket
 /// - mqtt: 1 socket
-const NET_SOCKETS: usize = 3 + 1;
+/// - syslog: 1 socket
+/// - udp control: 1 socket
+/// - ping: 1 socket
+/// - host presence: 1 socket
+const NET_SOCKETS: usize = 4 + 4;
 use crate::config::NET_CONFIG;
 
 pub async fn init(
     driver: wifi::Interface<'static>,
     rng: Rng,
+    settings: Settings,
 ) -> (
     net::Stack<'static>,
     net::Runner<'static, wifi::Interface<'static>>,
@@ -21,11 +28,57 @@ pub async fn init(
     let net_resources = Box::leak::<'static>(Box::new(net::StackResources::<NET_SOCKETS>::new()));
 
     let seed_64b = (rng.random() as u64) << 32 | rng.random() as u64;
-    let (net_stack, net_runner) = net::new(driver, NET_CONFIG.clone(), net_resources, seed_64b);
+    // A static IPv4 configuration set over MQTT takes over from the built-in one.
+    let mut config = NET_CONFIG.clone();
+    if let Some(static_v4) = settings.load::<StaticV4>(settings::STATIC_V4) {
+        config.ipv4 = static_v4.config();
+    }
+
+    let (net_stack, net_runner) = net::new(driver, config, net_resources, seed_64b);
 
     (net_stack, net_runner)
 }
 
+/// A static IPv4 configuration, in a form that can be sent over MQTT.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct StaticV4 {
+    pub address: [u8; 4],
+    pub prefix: u8,
+    pub gateway: Option<[u8; 4]>,
+    pub dns: Option<[u8; 4]>,
+}
+
+impl StaticV4 {
+    /// The stack's current IPv4 configuration, if it has one.
+    /// Only the first DNS server is reported.
+    pub fn current(stack: net::Stack<'static>) -> Option<Self> {
+        stack.config_v4().map(|config| StaticV4 {
+            address: config.address.address().octets(),
+            prefix: config.address.prefix_len(),
+            gateway: config.gateway.map(|gateway| gateway.octets()),
+            dns: config.dns_servers.first().map(|dns| dns.octets()),
+        })
+    }
+
+    /// Replaces the stack's IPv4 configuration. Store it in settings to keep it
+    /// past the next boot.
+    pub fn apply(&self, stack: net::Stack<'static>) {
+        stack.set_config_v4(self.config());
+    }
+
+    fn config(&self) -> net::ConfigV4 {
+        let mut config = net::StaticConfigV4 {
+            address: net::Ipv4Cidr::new(self.address.into(), self.prefix),
+            gateway: self.gateway.map(Into::into),
+            dns_servers: Default::default(),
+        };
+        if let Some(dns) = self.dns {
+            let _ = config.dns_servers.push(dns.into());
+        }
+        net::ConfigV4::Static(config)
+    }
+}
+
 /// Drives the network stack.
 #[embassy_executor::task]
 pub async fn stack_runner(mut runner: net::Runner<'static, wifi::Interface<'static>>) {
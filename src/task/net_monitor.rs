@@ -1,44 +1,91 @@
+use crate::settings::{self, Settings};
 use alloc::boxed::Box;
+use embassy_futures::select::{Either, select};
 use embassy_net as net;
 use embassy_sync::{blocking_mutex::raw::NoopRawMutex, watch};
-use embassy_time::{Duration, Timer};
+use embassy_time::{Duration, Instant, Timer};
 
-/// How often to check for changes in the network status.
-const NET_MONITOR_INTERVAL: Duration = Duration::from_secs(5);
+/// How often to check for changes in the network status, by default.
+const NET_MONITOR_INTERVAL_S: u32 = 5;
+/// Bounds for the interval when set at runtime.
+const NET_MONITOR_MIN_INTERVAL_S: u32 = 1;
+const NET_MONITOR_MAX_INTERVAL_S: u32 = 300;
+/// Poll faster for a while after boot and after the link comes back up, so the
+/// acquired address is reported quickly.
+const NET_MONITOR_FAST_INTERVAL: Duration = Duration::from_secs(1);
+const NET_MONITOR_FAST_PERIOD: Duration = Duration::from_secs(60);
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct NetworkStatus {
     pub link_up: bool,
     pub ip_config: Option<embassy_net::StaticConfigV4>,
+    pub ip6_config: Option<embassy_net::StaticConfigV6>,
 }
 
 pub type NetStatusWatch<const W: usize> = &'static watch::Watch<NoopRawMutex, NetworkStatus, W>;
 pub type NetStatusDynSender = watch::DynSender<'static, NetworkStatus>;
 pub type NetStatusDynReceiver = watch::DynReceiver<'static, NetworkStatus>;
 
+/// Polling interval for the network monitor, in seconds.
+pub type NetIntervalWatch<const W: usize> = &'static watch::Watch<NoopRawMutex, u32, W>;
+pub type NetIntervalDynSender = watch::DynSender<'static, u32>;
+pub type NetIntervalDynReceiver = watch::DynReceiver<'static, u32>;
+
 /// Takes a const that sets the maximum number of watchers.
-pub fn init<const WATCHERS: usize>() -> NetStatusWatch<WATCHERS> {
-    Box::leak(Box::new(watch::Watch::new()))
+pub fn init<const WATCHERS: usize>(
+    settings: Settings,
+) -> (NetStatusWatch<WATCHERS>, NetIntervalWatch<1>) {
+    let interval_s = settings
+        .load(settings::NET_INTERVAL)
+        .unwrap_or(NET_MONITOR_INTERVAL_S);
+    let netstatus_watch = Box::leak(Box::new(watch::Watch::new()));
+    let netinterval_watch = Box::leak(Box::new(watch::Watch::new_with(interval_s)));
+
+    (netstatus_watch, netinterval_watch)
 }
 
 // Monitors the network interface and signals changes.
 #[embassy_executor::task]
-pub async fn net_monitor(stack: net::Stack<'static>, netstatus_sender: NetStatusDynSender) {
+pub async fn net_monitor(
+    stack: net::Stack<'static>,
+    mut netinterval_receiver: NetIntervalDynReceiver,
+    netstatus_sender: NetStatusDynSender,
+) {
     let mut status = NetworkStatus {
         link_up: false,
         ip_config: None,
+        ip6_config: None,
     };
+    let mut interval_s = NET_MONITOR_INTERVAL_S;
+    let mut fast_until = Instant::now() + NET_MONITOR_FAST_PERIOD;
 
     loop {
-        Timer::after(NET_MONITOR_INTERVAL).await;
+        let interval = match Instant::now() < fast_until {
+            true => NET_MONITOR_FAST_INTERVAL,
+            false => Duration::from_secs(interval_s as u64),
+        };
+
+        if let Either::Second(new_interval_s) =
+            select(Timer::after(interval), netinterval_receiver.changed()).await
+        {
+            interval_s =
+                new_interval_s.clamp(NET_MONITOR_MIN_INTERVAL_S, NET_MONITOR_MAX_INTERVAL_S);
+            continue;
+        }
 
         let new_status = NetworkStatus {
             link_up: stack.is_link_up(),
             ip_config: stack.config_v4(),
+            ip6_config: stack.config_v6(),
         };
 
         // Notify if changed.
         if status != new_status {
+            // Reconnected: poll fast while DHCP runs.
+            if new_status.link_up && !status.link_up {
+                fast_until = Instant::now() + NET_MONITOR_FAST_PERIOD;
+            }
+
             netstatus_sender.send(new_status.clone());
             status = new_status;
         }
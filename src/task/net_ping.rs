@@ -0,0 +1,98 @@
+//! ICMP echo diagnostics, to tell a WiFi problem from an AP or upstream one.
+
+use crate::memlog::SharedLogger;
+use alloc::{boxed::Box, format};
+use embassy_net::{
+    Ipv4Address,
+    icmp::{
+        PacketMetadata,
+        ping::{PingManager, PingParams},
+    },
+};
+use embassy_sync::{blocking_mutex::raw::NoopRawMutex, channel};
+use embassy_time::{Duration, Timer};
+use serde::Deserialize;
+
+const PING_DEFAULT_COUNT: u16 = 4;
+const PING_MAX_COUNT: u16 = 20;
+const PING_TIMEOUT: Duration = Duration::from_secs(2);
+const PING_INTERVAL: Duration = Duration::from_secs(1);
+
+pub type NetPingChannel<const N: usize> = &'static channel::Channel<NoopRawMutex, PingRequest, N>;
+pub type NetPingDynSender = channel::DynamicSender<'static, PingRequest>;
+pub type NetPingDynReceiver = channel::DynamicReceiver<'static, PingRequest>;
+
+/// A request to ping an address, e.g. `{"target": [10, 0, 1, 1], "count": 4}`.
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub struct PingRequest {
+    pub target: [u8; 4],
+    pub count: Option<u16>,
+}
+
+#[must_use]
+pub fn init<const BACKLOG: usize>() -> NetPingChannel<BACKLOG> {
+    Box::leak(Box::new(channel::Channel::new()))
+}
+
+/// Pings addresses on request, and logs round-trip statistics for each run.
+#[embassy_executor::task]
+pub async fn net_ping(
+    stack: embassy_net::Stack<'static>,
+    netping_receiver: NetPingDynReceiver,
+    memlog: SharedLogger,
+) {
+    let mut rx_meta = [PacketMetadata::EMPTY; 1];
+    let mut rx_buffer = [0u8; 256];
+    let mut tx_meta = [PacketMetadata::EMPTY; 1];
+    let mut tx_buffer = [0u8; 256];
+    let mut ping_manager = PingManager::new(
+        stack,
+        &mut rx_meta,
+        &mut rx_buffer,
+        &mut tx_meta,
+        &mut tx_buffer,
+    );
+
+    loop {
+        let request = netping_receiver.receive().await;
+        let target = Ipv4Address::from(request.target);
+        let count = request
+            .count
+            .unwrap_or(PING_DEFAULT_COUNT)
+            .clamp(1, PING_MAX_COUNT);
+
+        let mut params = PingParams::new(target);
+        params.set_count(1).set_timeout(PING_TIMEOUT);
+
+        let mut received: u16 = 0;
+        let mut rtt_min = Duration::MAX;
+        let mut rtt_max = Duration::from_ticks(0);
+        let mut rtt_sum = Duration::from_ticks(0);
+
+        for seq in 0..count {
+            if seq > 0 {
+                Timer::after(PING_INTERVAL).await;
+            }
+
+            match ping_manager.ping(&params).await {
+                Ok(rtt) => {
+                    received += 1;
+                    rtt_min = rtt_min.min(rtt);
+                    rtt_max = rtt_max.max(rtt);
+                    rtt_sum += rtt;
+                }
+                Err(error) => memlog.debug(format!("ping: {target} seq {seq}: {error:?}")),
+            }
+        }
+
+        match received {
+            0 => memlog.warn(format!("ping: {target} 0/{count} replies")),
+            _ => memlog.info(format!(
+                "ping: {target} {received}/{count} replies, rtt min/avg/max {}/{}/{}ms",
+                rtt_min.as_millis(),
+                (rtt_sum / received as u32).as_millis(),
+                rtt_max.as_millis(),
+            )),
+        }
+    }
+}
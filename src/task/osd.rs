@@ -0,0 +1,134 @@
+use crate::{
+    memlog::SharedLogger,
+    task::pin_control::{
+        self, ButtonAck, ButtonAckSubscriber, ButtonPress, PinControlMessage, PinControlPublisher,
+    },
+};
+use alloc::{boxed::Box, format, string::String};
+use embassy_sync::{blocking_mutex::raw::NoopRawMutex, channel};
+use embassy_time::{Duration, Timer, with_timeout};
+use serde::{Deserialize, Serialize};
+
+pub type OsdChannel<const N: usize> = &'static channel::Channel<NoopRawMutex, OsdMacro, N>;
+pub type OsdDynSender = channel::DynamicSender<'static, OsdMacro>;
+pub type OsdDynReceiver = channel::DynamicReceiver<'static, OsdMacro>;
+
+// How long to wait for pin_control to acknowledge each press of a macro.
+const OSD_PRESS_ACK_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Named sequences of display-board button presses.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum OsdMacro {
+    BrightnessUp,
+    BrightnessDown,
+    InputNext,
+    CloseMenu,
+}
+
+struct MacroSpec {
+    presses: &'static [PinControlMessage],
+    // Pause between consecutive presses, so the OSD can keep up.
+    interval: Duration,
+}
+
+// The sequences assume the OSD opens with the brightness entry selected,
+// as on the installed controller. Other controller firmware may differ.
+const BRIGHTNESS_UP: MacroSpec = MacroSpec {
+    presses: &[
+        PinControlMessage::ButtonMenu,
+        PinControlMessage::ButtonMenu,
+        PinControlMessage::ButtonUp,
+        PinControlMessage::ButtonBack,
+        PinControlMessage::ButtonBack,
+    ],
+    interval: Duration::from_millis(400),
+};
+const BRIGHTNESS_DOWN: MacroSpec = MacroSpec {
+    presses: &[
+        PinControlMessage::ButtonMenu,
+        PinControlMessage::ButtonMenu,
+        PinControlMessage::ButtonDown,
+        PinControlMessage::ButtonBack,
+        PinControlMessage::ButtonBack,
+    ],
+    interval: Duration::from_millis(400),
+};
+const INPUT_NEXT: MacroSpec = MacroSpec {
+    presses: &[
+        PinControlMessage::ButtonMenu,
+        PinControlMessage::ButtonDown,
+        PinControlMessage::ButtonMenu,
+        PinControlMessage::ButtonDown,
+        PinControlMessage::ButtonMenu,
+        PinControlMessage::ButtonBack,
+        PinControlMessage::ButtonBack,
+    ],
+    interval: Duration::from_millis(600),
+};
+
+// Backs out of any open menu level, leaving the OSD closed.
+const CLOSE_MENU: MacroSpec = MacroSpec {
+    presses: &[
+        PinControlMessage::ButtonBack,
+        PinControlMessage::ButtonBack,
+        PinControlMessage::ButtonBack,
+    ],
+    interval: Duration::from_millis(300),
+};
+
+impl OsdMacro {
+    fn spec(&self) -> &'static MacroSpec {
+        match self {
+            OsdMacro::BrightnessUp => &BRIGHTNESS_UP,
+            OsdMacro::BrightnessDown => &BRIGHTNESS_DOWN,
+            OsdMacro::InputNext => &INPUT_NEXT,
+            OsdMacro::CloseMenu => &CLOSE_MENU,
+        }
+    }
+}
+
+#[must_use]
+pub fn init<const BACKLOG: usize>() -> OsdChannel<BACKLOG> {
+    Box::leak(Box::new(channel::Channel::new()))
+}
+
+/// Replays OSD macros as sequences of button presses.
+/// Each press must be acknowledged before the next, otherwise the macro is abandoned
+/// rather than leaving later presses to land on the wrong menu entry.
+#[embassy_executor::task]
+pub async fn osd_macro(
+    osd_receiver: OsdDynReceiver,
+    pincontrol_publisher: PinControlPublisher,
+    mut buttonack_subscriber: ButtonAckSubscriber,
+    memlog: SharedLogger,
+) {
+    loop {
+        let osd_macro = osd_receiver.receive().await;
+        let spec = osd_macro.spec();
+        memlog.info(format!("osd: running {osd_macro:?}"));
+
+        for (index, &press) in spec.presses.iter().enumerate() {
+            if index > 0 {
+                Timer::after(spec.interval).await;
+            }
+
+            let press = ButtonPress::new(press);
+            pincontrol_publisher.publish(press).await;
+
+            let ack_fut = pin_control::ack_for(&mut buttonack_subscriber, press.id);
+            let failure = match with_timeout(OSD_PRESS_ACK_TIMEOUT, ack_fut).await {
+                Ok(ButtonAck { result: Ok(()), .. }) => continue,
+                Ok(ButtonAck {
+                    result: Err(error), ..
+                }) => format!("{error:?}"),
+                Err(_timeout) => String::from("no acknowledgement"),
+            };
+
+            memlog.warn(format!(
+                "osd: {osd_macro:?} abandoned at press {}: {failure}",
+                index + 1
+            ));
+            break;
+        }
+    }
+}
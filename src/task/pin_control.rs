@@ -2,19 +2,34 @@ use crate::{
     driver::mcp23009::{OutputState, Pin},
     ioexpander::{self, IoExpander},
     memlog::SharedLogger,
-    task::buzzer::{BuzzerAction, BuzzerChannel, BuzzerPattern},
+    metrics::METRICS,
+    task::{
+        buzzer::{BuzzerChannel, NamedPattern},
+        power_relay::{PowerRelayStateDynReceiver, RelayStatus},
+        supervisor::{self, Heartbeat},
+    },
 };
 use alloc::{boxed::Box, format};
+use core::sync::atomic::{AtomicU32, Ordering};
 use embassy_futures::select::{Either, select};
 use embassy_sync::{blocking_mutex::raw::NoopRawMutex, pubsub, watch};
-use embassy_time::{Duration, Ticker};
+use embassy_time::{Duration, Instant, Ticker};
 use serde::{Deserialize, Serialize};
 
 // How long to toggle button control pins for.
 const BUTTON_DELAY_MS: Duration = Duration::from_millis(200);
+/// Upper bound on custom hold durations. Presses are handled one at a time,
+/// so a hold delays every press queued behind it.
+pub const MAX_BUTTON_HOLD_MS: u32 = 3_000;
+// Bounds on repeated presses, which keep a repeat shorter than the longest hold.
+const MAX_BUTTON_REPEAT: u8 = 5;
+const BUTTON_REPEAT_INTERVAL_MS: (u32, u32) = (100, 400);
 // Rate at which to poll the display LEDs.
 // 4 Hz keeps latency low while remaining negligible on CPU budget.
 const LED_POLL_INTERVAL: Duration = Duration::from_hz(4);
+// Minimum time between consecutive power button presses.
+// The controller can end up in an undefined power state if pulsed faster.
+const POWER_BUTTON_MIN_INTERVAL: Duration = Duration::from_secs(2);
 
 // Pin connections.
 const PIN_LED_GREEN: Pin = Pin::Gp0;
@@ -27,19 +42,20 @@ const PIN_BTN_BACK: Pin = Pin::Gp6;
 
 const PUBSUB_CAPACITY: usize = 5;
 pub type PinControlPubSub<const P: usize, const S: usize> =
-    &'static pubsub::PubSubChannel<NoopRawMutex, PinControlMessage, PUBSUB_CAPACITY, S, P>;
-pub type PinControlPublisher = pubsub::DynPublisher<'static, PinControlMessage>;
-pub type PinControlSubscriber = pubsub::DynSubscriber<'static, PinControlMessage>;
+    &'static pubsub::PubSubChannel<NoopRawMutex, ButtonPress, PUBSUB_CAPACITY, S, P>;
+pub type PinControlPublisher = pubsub::DynPublisher<'static, ButtonPress>;
+pub type PinControlSubscriber = pubsub::DynSubscriber<'static, ButtonPress>;
 
 pub type DisplayLedWatch<const W: usize> = &'static watch::Watch<NoopRawMutex, LedState, W>;
 pub type DisplayLedDynSender = watch::DynSender<'static, LedState>;
 pub type DisplayLedDynReceiver = watch::DynReceiver<'static, LedState>;
 
-const ERROR_PATTERN: BuzzerPattern = &[
-    BuzzerAction::Beep { ms: 120 },
-    BuzzerAction::Pause { ms: 80 },
-    BuzzerAction::Beep { ms: 350 },
-];
+// Every subscriber sees every ack, so acks for concurrent presses can't overwrite
+// each other. Holds one ack per queued press; older acks are dropped first.
+pub type ButtonAckPubSub<const S: usize> =
+    &'static pubsub::PubSubChannel<NoopRawMutex, ButtonAck, PUBSUB_CAPACITY, S, 1>;
+pub type ButtonAckPublisher = pubsub::DynImmediatePublisher<'static, ButtonAck>;
+pub type ButtonAckSubscriber = pubsub::DynSubscriber<'static, ButtonAck>;
 
 #[allow(clippy::enum_variant_names)]
 #[derive(Copy, Clone, Serialize, Deserialize)]
@@ -51,6 +67,173 @@ pub enum PinControlMessage {
     ButtonBack,
     ButtonDown,
     ButtonUp,
+    // Presses a button for a custom duration.
+    ButtonHold {
+        button: Button,
+        hold_ms: u32,
+    },
+    // Presses a button several times, e.g. to move an OSD slider by more than one step.
+    ButtonRepeat {
+        button: Button,
+        count: u8,
+        interval_ms: u32,
+    },
+}
+
+// Identifies the next press, so its sender can pick out its own acknowledgement.
+static NEXT_PRESS_ID: AtomicU32 = AtomicU32::new(0);
+
+/// A button press as sent to pin_control.
+#[derive(Copy, Clone)]
+pub struct ButtonPress {
+    pub id: u32,
+    pub message: PinControlMessage,
+}
+
+impl ButtonPress {
+    /// A press with a fresh id.
+    pub fn new(message: PinControlMessage) -> Self {
+        ButtonPress {
+            id: NEXT_PRESS_ID.fetch_add(1, Ordering::Relaxed),
+            message,
+        }
+    }
+}
+
+/// Display-board buttons.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Button {
+    Power,
+    Menu,
+    Back,
+    Down,
+    Up,
+}
+
+impl Button {
+    fn pin(self) -> Pin {
+        match self {
+            Button::Power => PIN_BTN_POWER,
+            Button::Up => PIN_BTN_UP,
+            Button::Down => PIN_BTN_DOWN,
+            Button::Menu => PIN_BTN_MENU,
+            Button::Back => PIN_BTN_BACK,
+        }
+    }
+}
+
+impl PinControlMessage {
+    /// The button this message presses.
+    pub fn button(&self) -> Button {
+        match self {
+            PinControlMessage::ButtonPower => Button::Power,
+            PinControlMessage::ButtonMenu => Button::Menu,
+            PinControlMessage::ButtonBack => Button::Back,
+            PinControlMessage::ButtonDown => Button::Down,
+            PinControlMessage::ButtonUp => Button::Up,
+            PinControlMessage::ButtonHold { button, .. } => *button,
+            PinControlMessage::ButtonRepeat { button, .. } => *button,
+        }
+    }
+
+    /// How many times to press the button.
+    fn presses(&self) -> u8 {
+        match self {
+            PinControlMessage::ButtonRepeat { count, .. } => (*count).clamp(1, MAX_BUTTON_REPEAT),
+            _ => 1,
+        }
+    }
+
+    /// How long to wait between repeated presses.
+    fn repeat_interval(&self) -> Duration {
+        let (min_ms, max_ms) = BUTTON_REPEAT_INTERVAL_MS;
+        match self {
+            PinControlMessage::ButtonRepeat { interval_ms, .. } => {
+                Duration::from_millis((*interval_ms).clamp(min_ms, max_ms) as u64)
+            }
+            _ => Duration::from_millis(0),
+        }
+    }
+
+    /// How long to hold the button down for.
+    fn hold(&self) -> Duration {
+        match self {
+            PinControlMessage::ButtonHold { hold_ms, .. } => {
+                Duration::from_millis((*hold_ms).min(MAX_BUTTON_HOLD_MS) as u64)
+            }
+            _ => BUTTON_DELAY_MS,
+        }
+    }
+}
+
+/// Reasons for refusing a button press.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InterlockError {
+    /// The board has no DC power, so the press would have no effect.
+    RelayNotClosed,
+    /// The power button was pressed too recently.
+    PowerButtonTooSoon,
+    /// The power button can only be pressed once at a time.
+    PowerButtonRepeat,
+}
+
+/// Soft interlocks guarding the display-board buttons against nonsense
+/// command sequences.
+struct Interlock {
+    last_power_press: Option<Instant>,
+}
+
+impl Interlock {
+    fn new() -> Self {
+        Self {
+            last_power_press: None,
+        }
+    }
+
+    /// Checks whether a button press is allowed, and records it if so.
+    fn check(
+        &mut self,
+        message: PinControlMessage,
+        relay_state: Option<RelayStatus>,
+    ) -> Result<(), InterlockError> {
+        if message.button() != Button::Power {
+            return Ok(());
+        }
+
+        if message.presses() > 1 {
+            return Err(InterlockError::PowerButtonRepeat);
+        }
+
+        if relay_state != Some(RelayStatus::Closed) {
+            return Err(InterlockError::RelayNotClosed);
+        }
+
+        if let Some(last_press) = self.last_power_press
+            && last_press.elapsed() < POWER_BUTTON_MIN_INTERVAL
+        {
+            return Err(InterlockError::PowerButtonTooSoon);
+        }
+
+        self.last_power_press = Some(Instant::now());
+        Ok(())
+    }
+}
+
+/// Why a button press did not complete.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PressError {
+    /// An interlock refused the press.
+    Refused(InterlockError),
+    /// The IO expander failed while actuating the button.
+    Bus,
+}
+
+/// Signals that a button press was handled, once its pin is released.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ButtonAck {
+    /// The id of the acknowledged `ButtonPress`.
+    pub id: u32,
+    pub result: Result<(), PressError>,
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
@@ -59,12 +242,33 @@ pub struct LedState {
     pub green: bool,
 }
 
-pub fn init<'d, const PUBS: usize, const SUBS: usize, const WATCHERS: usize>()
--> (PinControlPubSub<PUBS, SUBS>, DisplayLedWatch<WATCHERS>) {
+pub fn init<
+    'd,
+    const PUBS: usize,
+    const SUBS: usize,
+    const WATCHERS: usize,
+    const ACK_SUBS: usize,
+>() -> (
+    PinControlPubSub<PUBS, SUBS>,
+    DisplayLedWatch<WATCHERS>,
+    ButtonAckPubSub<ACK_SUBS>,
+) {
     let pincontrol_pubsub = Box::leak(Box::new(pubsub::PubSubChannel::new()));
     let display_led_watch = Box::leak(Box::new(watch::Watch::new()));
+    let button_ack_pubsub = Box::leak(Box::new(pubsub::PubSubChannel::new()));
+
+    (pincontrol_pubsub, display_led_watch, button_ack_pubsub)
+}
 
-    (pincontrol_pubsub, display_led_watch)
+/// Waits for the acknowledgement of press `id`, skipping acks for other presses.
+pub async fn ack_for(subscriber: &mut ButtonAckSubscriber, id: u32) -> ButtonAck {
+    loop {
+        // A lagging subscriber only loses acks older than the ones it waits on.
+        let ack = subscriber.next_message_pure().await;
+        if ack.id == id {
+            return ack;
+        }
+    }
 }
 
 impl IoExpander {
@@ -80,17 +284,16 @@ impl IoExpander {
         &mut self,
         message: PinControlMessage,
     ) -> Result<(), ioexpander::Error> {
-        let pin = match message {
-            PinControlMessage::ButtonPower => PIN_BTN_POWER,
-            PinControlMessage::ButtonUp => PIN_BTN_UP,
-            PinControlMessage::ButtonDown => PIN_BTN_DOWN,
-            PinControlMessage::ButtonMenu => PIN_BTN_MENU,
-            PinControlMessage::ButtonBack => PIN_BTN_BACK,
-        };
-
-        self.driver.set_output(pin, OutputState::Low)?;
-        embassy_time::Timer::after(BUTTON_DELAY_MS).await;
-        self.driver.set_output(pin, OutputState::Released)?;
+        let pin = message.button().pin();
+
+        for press in 0..message.presses() {
+            if press > 0 {
+                embassy_time::Timer::after(message.repeat_interval()).await;
+            }
+            self.driver.set_output(pin, OutputState::Low)?;
+            embassy_time::Timer::after(message.hold()).await;
+            self.driver.set_output(pin, OutputState::Released)?;
+        }
 
         Ok(())
     }
@@ -102,14 +305,19 @@ pub async fn pin_control(
     mut ioexpander: IoExpander,
     mut pincontrol_subscriber: PinControlSubscriber,
     display_led_sender: DisplayLedDynSender,
+    button_ack_publisher: ButtonAckPublisher,
+    mut powerrelay_receiver: PowerRelayStateDynReceiver,
     buzzer_channel: BuzzerChannel,
     memlog: SharedLogger,
 ) {
+    let mut interlock = Interlock::new();
     let mut led_state: Option<LedState> = None;
     let mut fault_active = false;
     let mut led_poll_ticker = Ticker::every(LED_POLL_INTERVAL);
 
     loop {
+        supervisor::check_in(Heartbeat::PinControl);
+
         let catch = (async || -> Result<(), ioexpander::Error> {
             let ticker_fut = led_poll_ticker.next();
             let pincontrol_fut = pincontrol_subscriber.next_message();
@@ -126,8 +334,27 @@ pub async fn pin_control(
 
                 // Control message received, press a button pin.
                 Either::Second(result) => {
-                    if let pubsub::WaitResult::Message(message) = result {
-                        ioexpander.press_button(message).await?;
+                    if let pubsub::WaitResult::Message(ButtonPress { id, message }) = result {
+                        let button = message.button();
+                        match interlock.check(message, powerrelay_receiver.try_get()) {
+                            Ok(()) => {
+                                let press = ioexpander.press_button(message).await;
+                                if press.is_ok() {
+                                    for _ in 0..message.presses() {
+                                        METRICS.button_pressed(button);
+                                    }
+                                }
+                                let result =
+                                    press.as_ref().map(|_| ()).map_err(|_| PressError::Bus);
+                                button_ack_publisher.publish_immediate(ButtonAck { id, result });
+                                press?;
+                            }
+                            Err(reason) => {
+                                let result = Err(PressError::Refused(reason));
+                                button_ack_publisher.publish_immediate(ButtonAck { id, result });
+                                memlog.warn(format!("pinctl: press refused: {reason:?}"));
+                            }
+                        }
                     }
                 }
             }
@@ -140,7 +367,7 @@ pub async fn pin_control(
             // New fault.
             (Err(error), false) => {
                 fault_active = true;
-                buzzer_channel.send(ERROR_PATTERN).await;
+                buzzer_channel.send(NamedPattern::Error.into()).await;
                 memlog.warn(format!("pinctl: mcp23009 fault: {error}"));
 
                 let _ = ioexpander.configure();
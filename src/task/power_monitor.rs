@@ -0,0 +1,79 @@
+//! Display power draw, from a current-sense amplifier on the 24V rail.
+//!
+//! Expects a high-side shunt with an INA18x-style amplifier whose output feeds the
+//! board's `sense_current` ADC pin. Change `CURRENT_SENSE_MV_PER_A` to match the
+//! shunt and gain fitted, and set `CURRENT_SENSOR_FITTED` to run the task.
+
+use crate::board::CurrentSensePin;
+use alloc::boxed::Box;
+use embassy_sync::{blocking_mutex::raw::NoopRawMutex, watch};
+use embassy_time::{Duration, Ticker};
+use esp_hal::{
+    Async,
+    analog::adc::{Adc, AdcCalLine, AdcConfig, AdcPin, Attenuation},
+    peripherals::ADC1,
+};
+use serde::Serialize;
+
+/// Whether the current-sense amplifier is fitted. Without it, the ADC pin floats
+/// and the readings are meaningless, so the monitor is not started.
+pub const CURRENT_SENSOR_FITTED: bool = false;
+// 10mΩ shunt and a gain of 20 (INA181A1): 200mV per amp.
+const CURRENT_SENSE_MV_PER_A: u32 = 200;
+// Nominal rail voltage, as only the current is measured.
+const RAIL_VOLTAGE_MV: u32 = 24_000;
+// Readings are averaged over this many samples, one per interval.
+const SAMPLE_INTERVAL: Duration = Duration::from_millis(125);
+const SAMPLES_PER_READING: u32 = 8;
+
+pub type PowerDrawWatch<const W: usize> = &'static watch::Watch<NoopRawMutex, PowerDraw, W>;
+pub type PowerDrawDynSender = watch::DynSender<'static, PowerDraw>;
+pub type PowerDrawDynReceiver = watch::DynReceiver<'static, PowerDraw>;
+
+/// Average draw of the display over one reading.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub struct PowerDraw {
+    pub current_ma: u32,
+    pub power_mw: u32,
+}
+
+impl PowerDraw {
+    fn from_sense_mv(sense_mv: u32) -> Self {
+        let current_ma = sense_mv * 1000 / CURRENT_SENSE_MV_PER_A;
+        PowerDraw {
+            current_ma,
+            power_mw: current_ma * RAIL_VOLTAGE_MV / 1000,
+        }
+    }
+}
+
+/// Takes a const that sets the maximum number of watchers.
+pub fn init<const WATCHERS: usize>() -> PowerDrawWatch<WATCHERS> {
+    Box::leak(Box::new(watch::Watch::new()))
+}
+
+/// Samples the current-sense amplifier and publishes the averaged draw.
+#[embassy_executor::task]
+pub async fn power_monitor(
+    adc1: ADC1<'static>,
+    sense_pin: CurrentSensePin,
+    powerdraw_sender: PowerDrawDynSender,
+) {
+    let mut adc_config = AdcConfig::new();
+    // 11dB attenuation covers up to ~2.5V of sense output, or 12.5A at 200mV/A.
+    let mut sense_pin: AdcPin<_, ADC1, AdcCalLine<ADC1>> =
+        adc_config.enable_pin_with_cal(sense_pin, Attenuation::_11dB);
+    let mut adc: Adc<'static, ADC1, Async> = Adc::new(adc1, adc_config).into_async();
+
+    let mut ticker = Ticker::every(SAMPLE_INTERVAL);
+    loop {
+        let mut sum_mv = 0;
+        for _ in 0..SAMPLES_PER_READING {
+            ticker.next().await;
+            // Calibrated readings are in millivolts.
+            sum_mv += adc.read_oneshot(&mut sense_pin).await as u32;
+        }
+
+        powerdraw_sender.send(PowerDraw::from_sense_mv(sum_mv / SAMPLES_PER_READING));
+    }
+}
@@ -2,6 +2,7 @@
 use alloc::boxed::Box;
 use embassy_sync::{blocking_mutex::raw::NoopRawMutex, channel, watch};
 use esp_hal::gpio;
+use serde::{Deserialize, Serialize};
 
 pub type PowerRelayChannel<const N: usize> =
     &'static channel::Channel<NoopRawMutex, RelayCommand, N>;
@@ -12,11 +13,13 @@ pub type PowerRelayWatch<const W: usize> = &'static watch::Watch<NoopRawMutex, R
 pub type PowerRelayStateDynSender = watch::DynSender<'static, RelayStatus>;
 pub type PowerRelayStateDynReceiver = watch::DynReceiver<'static, RelayStatus>;
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub enum RelayCommand {
     Open,
     Close,
     ForceOpenLatch,
+    // Clears a forced-open latch, leaving the relay open.
+    ResetLatch,
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
@@ -48,14 +51,21 @@ pub async fn power_relay(
     loop {
         let command = relay_receiver.receive().await;
 
-        if state != RelayStatus::ForcedOpen {
+        if state == RelayStatus::ForcedOpen {
+            // A latched relay only accepts an explicit reset.
+            if command == RelayCommand::ResetLatch {
+                state = RelayStatus::Open;
+                pin_power_display_relay.set_low();
+                relay_state_sender.send(state);
+            }
+        } else {
             match command {
                 RelayCommand::Close => {
                     state = RelayStatus::Closed;
                     pin_power_display_relay.set_high();
                 }
 
-                RelayCommand::Open => {
+                RelayCommand::Open | RelayCommand::ResetLatch => {
                     state = RelayStatus::Open;
                     pin_power_display_relay.set_low();
                 }
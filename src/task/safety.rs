@@ -1,32 +1,33 @@
 use crate::{
+    audit::{self, Source},
     memlog::SharedLogger,
     task::{
-        buzzer::{BuzzerAction, BuzzerChannel, BuzzerPattern},
+        buzzer::{BuzzerChannel, NamedPattern},
         fan_control::{FAN_TACHY_MEASURE_INTERVAL, FanDutyDynSender, FanTachyDynReceiver},
         power_relay::{PowerRelayDynSender, RelayCommand},
         temp_sensor::TempSensorDynReceiver,
     },
 };
 use alloc::format;
+use core::sync::atomic::{AtomicBool, Ordering};
 use embassy_futures::select::{Either, select};
 use embassy_time::{Duration, Instant, with_timeout};
 
 // Trip the relay if temperature exceeds this.
 const MAX_SAFE_TEMP_C: f32 = 85.0;
+// A tripped relay may only be reset once the temperature is this far below the trip point.
+const LATCH_RESET_MARGIN_C: f32 = 5.0;
 // Trip the relay if temp sensor fails and fan tachy is below this.
 const MIN_SAFE_FAN_RPM: u16 = 2000;
 
-const SAFETY_ALARM_PATTERN: BuzzerPattern = &[
-    BuzzerAction::Beep { ms: 320 },
-    BuzzerAction::Pause { ms: 100 },
-    BuzzerAction::Beep { ms: 320 },
-    BuzzerAction::Pause { ms: 100 },
-    BuzzerAction::Beep { ms: 320 },
-    BuzzerAction::Pause { ms: 100 },
-    BuzzerAction::Beep { ms: 320 },
-    BuzzerAction::Pause { ms: 100 },
-    BuzzerAction::Beep { ms: 320 },
-];
+// Set while the last temperature reading allows a relay latch reset.
+static TEMPERATURE_SAFE: AtomicBool = AtomicBool::new(false);
+
+/// Whether the display has cooled enough for a latched relay to be reset.
+/// False until the first good reading, and while readings are missing.
+pub fn temperature_safe() -> bool {
+    TEMPERATURE_SAFE.load(Ordering::Relaxed)
+}
 
 #[embassy_executor::task]
 pub async fn watchdog(
@@ -62,10 +63,15 @@ pub async fn watchdog(
                 if let Ok(temp_c) = reading.temperature {
                     missing_temp_deadline = reading.timestamp + missing_temp_window;
                     fan_park_sent_since_last_good_temp = false;
+                    TEMPERATURE_SAFE.store(
+                        temp_c <= MAX_SAFE_TEMP_C - LATCH_RESET_MARGIN_C,
+                        Ordering::Relaxed,
+                    );
 
                     if temp_c > MAX_SAFE_TEMP_C {
+                        audit::commanded(Source::Protection);
                         powerrelay_sender.send(RelayCommand::ForceOpenLatch).await;
-                        buzzer_channel.send(SAFETY_ALARM_PATTERN).await;
+                        buzzer_channel.send(NamedPattern::Alarm.into()).await;
                         memlog.warn(format!("safety: overtemp {temp_c:.1}c"));
                     }
                 }
@@ -78,6 +84,7 @@ pub async fn watchdog(
 
             Err(_timeout) => {
                 missing_temp_deadline = Instant::now() + missing_temp_window;
+                TEMPERATURE_SAFE.store(false, Ordering::Relaxed);
 
                 if !fan_park_sent_since_last_good_temp {
                     fanduty_sender.send(100);
@@ -90,8 +97,9 @@ pub async fn watchdog(
                     .unwrap_or(false);
 
                 if !(tachy_fresh && last_tachy_rpm > MIN_SAFE_FAN_RPM) {
+                    audit::commanded(Source::Protection);
                     powerrelay_sender.send(RelayCommand::ForceOpenLatch).await;
-                    buzzer_channel.send(SAFETY_ALARM_PATTERN).await;
+                    buzzer_channel.send(NamedPattern::Alarm.into()).await;
                     memlog.warn(format!(
                         "watchdog: no temp, unsafe fan tachy ({last_tachy_rpm}rpm)"
                     ));
@@ -6,7 +6,11 @@ use super::{
     power_relay::{PowerRelayDynSender, PowerRelayStateDynReceiver, RelayCommand, RelayStatus},
     temp_sensor::{TempSensorDynReceiver, TemperatureReading},
 };
-use crate::memlog::SharedLogger;
+use crate::{
+    audit::{self, Source},
+    commands::{self, Rejected},
+    memlog::SharedLogger,
+};
 use alloc::{boxed::Box, format};
 use embassy_sync::{blocking_mutex::raw::NoopRawMutex, channel, signal};
 use embassy_time::{Duration, Timer};
@@ -412,14 +416,14 @@ mod app {
 
         fn activate_selected_button(&mut self) {
             let button = &BUTTONS[self.selected_button];
-            if self
-                .pincontrol_publisher
-                .try_publish(button.message)
-                .is_ok()
-            {
-                self.status = format!("btn {}", button.label);
-            } else {
-                self.status = String::from("btn queue full");
+            if commands::check_button(button.message).is_err() {
+                self.status = String::from("power seq busy");
+                return;
+            }
+            audit::commanded(Source::SerialTui);
+            match commands::send_button(self.pincontrol_publisher, button.message) {
+                Ok(()) => self.status = format!("btn {}", button.label),
+                Err(rejected) => self.status = String::from(rejected.as_str()),
             }
         }
 
@@ -439,21 +443,35 @@ mod app {
             let command = match self.relay_state {
                 Some(RelayStatus::Open) => RelayCommand::Close,
                 Some(RelayStatus::Closed) => RelayCommand::Open,
-                Some(RelayStatus::ForcedOpen) => {
-                    self.status = String::from("relay latched");
-                    return;
-                }
+                Some(RelayStatus::ForcedOpen) => RelayCommand::ResetLatch,
                 None => {
                     self.status = String::from("relay unknown");
                     return;
                 }
             };
 
+            match commands::check_relay(command) {
+                Ok(()) => (),
+                Err(Rejected::PowerSequenceBusy) => {
+                    self.status = String::from("power seq busy");
+                    return;
+                }
+                Err(rejected) => {
+                    self.status = String::from(rejected.as_str());
+                    return;
+                }
+            }
+
+            audit::commanded(Source::SerialTui);
             match self.powerrelay_sender.try_send(command) {
                 Ok(()) => {
                     self.status = match command {
                         RelayCommand::Close => String::from("relay on"),
                         RelayCommand::Open => String::from("relay off"),
+                        RelayCommand::ResetLatch => {
+                            self.memlog.warn("uart: relay latch reset");
+                            String::from("relay unlatched")
+                        }
                         RelayCommand::ForceOpenLatch => unreachable!(),
                     };
                 }
@@ -664,7 +682,10 @@ mod app {
                     .map(|config| config.address.address())
                 {
                     Some(address) => format!("up {address}"),
-                    None => String::from("up no-ip"),
+                    None => match net_status.ip6_config.as_ref() {
+                        Some(config) => format!("up {}", config.address.address()),
+                        None => String::from("up no-ip"),
+                    },
                 },
                 None => String::from("--"),
             };
@@ -687,7 +708,9 @@ mod app {
                 Some(RelayStatus::Closed) => {
                     format_focus_button("off", self.focus == Focus::RelayToggle)
                 }
-                Some(RelayStatus::ForcedOpen) => String::from("[locked]"),
+                Some(RelayStatus::ForcedOpen) => {
+                    format_focus_button("reset", self.focus == Focus::RelayToggle)
+                }
                 None => String::from("[wait]"),
             };
 
@@ -701,7 +724,7 @@ mod app {
                         format!("temp {:>4.1}c r{}", temp_c, reading.retries)
                     }
                     Ok(temp_c) => format!("temp {:>4.1}c", temp_c),
-                    Err(_) => format!("temp err r{}", reading.retries),
+                    Err(_) => format!("temp err x{}", reading.health.consecutive_failures),
                 },
                 None => String::from("temp --.-c"),
             }
@@ -0,0 +1,92 @@
+use crate::{crash, memlog::SharedLogger, task::pin_control};
+use alloc::format;
+use core::sync::atomic::{AtomicU32, Ordering};
+use embassy_time::{Duration, Instant, Ticker};
+use esp_hal::{
+    peripherals::TIMG1,
+    time,
+    timer::timg::{MwdtStage, Wdt},
+};
+
+// How often to check heartbeats and feed the hardware watchdog.
+const SUPERVISOR_INTERVAL: Duration = Duration::from_secs(1);
+// Reset if the supervisor itself stops running for this long.
+const HARDWARE_WATCHDOG_TIMEOUT: time::Duration = time::Duration::from_secs(10);
+
+/// Tasks that must keep checking in with the supervisor.
+#[derive(Copy, Clone, Debug)]
+pub enum Heartbeat {
+    PinControl,
+    TempSensor,
+    FanTachy,
+}
+
+impl Heartbeat {
+    const ALL: [Heartbeat; 3] = [
+        Heartbeat::PinControl,
+        Heartbeat::TempSensor,
+        Heartbeat::FanTachy,
+    ];
+
+    /// How long the task may go without checking in before it is considered stalled.
+    fn max_silence(self) -> Duration {
+        match self {
+            // Polls LEDs at 4 Hz, but checks in only between presses, and the longest
+            // press is a custom hold. Repeats are bounded below that.
+            Heartbeat::PinControl => {
+                Duration::from_millis(pin_control::MAX_BUTTON_HOLD_MS as u64)
+                    + Duration::from_secs(5)
+            }
+            // One reading every 5s, plus measurement time and checksum retries.
+            Heartbeat::TempSensor => Duration::from_secs(30),
+            // One measurement every 10s, plus the capture window.
+            Heartbeat::FanTachy => Duration::from_secs(30),
+        }
+    }
+}
+
+// Milliseconds since boot of each task's last check-in, indexed by `Heartbeat as usize`.
+// Wraps after ~49 days, so compare with `wrapping_sub`.
+static LAST_CHECK_IN_MS: [AtomicU32; Heartbeat::ALL.len()] =
+    [const { AtomicU32::new(0) }; Heartbeat::ALL.len()];
+
+fn now_ms() -> u32 {
+    Instant::now().as_millis() as u32
+}
+
+/// Tells the supervisor that `task` is still making progress.
+pub fn check_in(task: Heartbeat) {
+    LAST_CHECK_IN_MS[task as usize].store(now_ms(), Ordering::Relaxed);
+}
+
+/// Feeds the hardware watchdog for as long as every critical task keeps checking in.
+///
+/// If a task stalls, records it in the crash log and resets. If the executor itself
+/// wedges, the hardware watchdog resets the chip instead.
+#[embassy_executor::task]
+pub async fn supervisor(mut wdt: Wdt<TIMG1<'static>>, memlog: SharedLogger) {
+    // Tasks start out checked in, so they have a full window to get going.
+    Heartbeat::ALL.into_iter().for_each(check_in);
+
+    wdt.set_timeout(MwdtStage::Stage0, HARDWARE_WATCHDOG_TIMEOUT);
+    wdt.enable();
+
+    let mut ticker = Ticker::every(SUPERVISOR_INTERVAL);
+    loop {
+        ticker.next().await;
+
+        let now = now_ms();
+        let stalled = Heartbeat::ALL.into_iter().find(|&task| {
+            let silence_ms =
+                now.wrapping_sub(LAST_CHECK_IN_MS[task as usize].load(Ordering::Relaxed));
+            silence_ms as u64 > task.max_silence().as_millis()
+        });
+
+        if let Some(task) = stalled {
+            memlog.error(format!("supervisor: {task:?} stalled, resetting"));
+            crash::reset_with_report(format_args!("supervisor: {task:?} stalled"));
+        }
+
+        wdt.feed();
+    }
+}
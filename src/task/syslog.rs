@@ -0,0 +1,108 @@
+use crate::{
+    config::MQTT_CLIENT_ID,
+    memlog::{Level, Record, SharedLogger},
+};
+use alloc::{format, string::String, vec::Vec};
+use embassy_net::{
+    IpEndpoint,
+    dns::DnsQueryType,
+    udp::{PacketMetadata, UdpSocket},
+};
+use embassy_time::Timer;
+
+// Set to None to disable log forwarding.
+const SYSLOG_SERVER_ADDR: Option<&str> = Some("syslog.abu");
+const SYSLOG_PORT: u16 = 514;
+const SYSLOG_APP_NAME: &str = "imac5k";
+// local0
+const SYSLOG_FACILITY: u8 = 16;
+
+// Maps a memlog level to a syslog severity.
+fn severity(level: Level) -> u8 {
+    match level {
+        Level::Error => 3,
+        Level::Warn => 4,
+        Level::Info => 6,
+        Level::Debug | Level::Trace => 7,
+    }
+}
+
+// Formats a record as an RFC 5424 message. We have no wall clock, so the
+// timestamp is left nil and the uptime goes into the message.
+// The record's tag goes into MSGID, for the server to filter on.
+fn format_record(record: &Record) -> String {
+    let priority = SYSLOG_FACILITY * 8 + severity(record.level);
+    let msgid = record.tag().unwrap_or("-");
+    format!(
+        "<{priority}>1 - {MQTT_CLIENT_ID} {SYSLOG_APP_NAME} - {msgid} [meta sequenceId=\"{}\"] {}",
+        record.seq, record
+    )
+}
+
+/// Forwards new memlog records to a remote syslog server over UDP.
+#[embassy_executor::task]
+pub async fn syslog(stack: embassy_net::Stack<'static>, memlog: SharedLogger) {
+    let Some(server_name) = SYSLOG_SERVER_ADDR else {
+        return;
+    };
+
+    let server_addr = 'dns: loop {
+        match stack.dns_query(server_name, DnsQueryType::A).await {
+            Ok(mut dns_result) => match dns_result.pop() {
+                Some(addr) => break 'dns addr,
+                None => memlog.warn("syslog: empty dns response"),
+            },
+            Err(_) => memlog.debug("syslog: failed to resolve server address"),
+        };
+
+        // Retry DNS request every minute, to keep from flooding the log.
+        Timer::after_secs(60).await;
+    };
+    let server = IpEndpoint::new(server_addr, SYSLOG_PORT);
+
+    let mut rx_meta = [PacketMetadata::EMPTY; 1];
+    let mut rx_buffer = [0u8; 16];
+    let mut tx_meta = [PacketMetadata::EMPTY; 4];
+    let mut tx_buffer = [0u8; 1024];
+    let mut socket = UdpSocket::new(
+        stack,
+        &mut rx_meta,
+        &mut rx_buffer,
+        &mut tx_meta,
+        &mut tx_buffer,
+    );
+    if let Err(error) = socket.bind(0) {
+        memlog.warn(format!("syslog: failed to bind socket: {error:?}"));
+        return;
+    }
+
+    memlog.enable_watch();
+    let Some(mut logwatch_receiver) = memlog.watch() else {
+        memlog.warn("syslog: no log watchers left");
+        return;
+    };
+
+    // Forward everything from here on. next_seq is the number the next record
+    // will get, so records already stored are not sent.
+    let mut next_seq = memlog.stats().next_seq;
+
+    loop {
+        logwatch_receiver.changed().await;
+
+        // The watch only holds the latest record, so pick up everything
+        // newer than what we sent from storage instead.
+        let messages: Vec<_> = memlog
+            .records()
+            .iter()
+            .rev()
+            .filter(|record| record.is_since(next_seq))
+            .map(|record| (record.seq, format_record(record)))
+            .collect();
+
+        for (seq, message) in messages {
+            // UDP is lossy anyway, so a failed send is just dropped.
+            let _ = socket.send_to(message.as_bytes(), server).await;
+            next_seq = seq.wrapping_add(1);
+        }
+    }
+}
@@ -0,0 +1,104 @@
+use crate::task::temp_sensor::TempSensorDynReceiver;
+use core::cell::RefCell;
+use critical_section::Mutex;
+use embassy_time::{Duration, Ticker};
+use serde::Serialize;
+
+/// How often to sample the temperature into the history.
+const TELEMETRY_SAMPLE_INTERVAL: Duration = Duration::from_secs(10);
+/// One hour of samples.
+const TELEMETRY_HISTORY_LEN: usize = 360;
+
+static TEMP_HISTORY: Mutex<RefCell<History>> = Mutex::new(RefCell::new(History::new()));
+
+/// Temperature statistics over the last minute, 10 minutes and hour.
+#[derive(Clone, Copy, Debug, Serialize)]
+pub struct TempHistory {
+    pub last_1m: WindowStats,
+    pub last_10m: WindowStats,
+    pub last_1h: WindowStats,
+}
+
+/// Statistics over the valid samples in a window. All None if there were none.
+#[derive(Clone, Copy, Debug, Default, Serialize)]
+pub struct WindowStats {
+    pub samples: usize,
+    pub min_c: Option<f32>,
+    pub max_c: Option<f32>,
+    pub avg_c: Option<f32>,
+}
+
+// A ring of samples, with NaN marking an interval without a valid reading.
+struct History {
+    samples: [f32; TELEMETRY_HISTORY_LEN],
+    len: usize,
+    next: usize,
+}
+
+impl History {
+    const fn new() -> Self {
+        History {
+            samples: [f32::NAN; TELEMETRY_HISTORY_LEN],
+            len: 0,
+            next: 0,
+        }
+    }
+
+    fn push(&mut self, sample: f32) {
+        self.samples[self.next] = sample;
+        self.next = (self.next + 1) % TELEMETRY_HISTORY_LEN;
+        self.len = (self.len + 1).min(TELEMETRY_HISTORY_LEN);
+    }
+
+    // Stats over the most recent samples covering `window`.
+    fn window(&self, window: Duration) -> WindowStats {
+        let count = (window.as_ticks() / TELEMETRY_SAMPLE_INTERVAL.as_ticks()) as usize;
+        let recent = (1..=count.min(self.len))
+            .map(|age| {
+                self.samples[(self.next + TELEMETRY_HISTORY_LEN - age) % TELEMETRY_HISTORY_LEN]
+            })
+            .filter(|sample| !sample.is_nan());
+
+        let mut stats = WindowStats::default();
+        let mut sum = 0.0;
+        for sample in recent {
+            stats.samples += 1;
+            stats.min_c = Some(stats.min_c.map_or(sample, |min| min.min(sample)));
+            stats.max_c = Some(stats.max_c.map_or(sample, |max| max.max(sample)));
+            sum += sample;
+        }
+        if stats.samples > 0 {
+            stats.avg_c = Some(sum / stats.samples as f32);
+        }
+        stats
+    }
+}
+
+/// Summarizes the temperature history.
+pub fn temp_history() -> TempHistory {
+    critical_section::with(|cs| {
+        let history = TEMP_HISTORY.borrow_ref(cs);
+        TempHistory {
+            last_1m: history.window(Duration::from_secs(60)),
+            last_10m: history.window(Duration::from_secs(10 * 60)),
+            last_1h: history.window(Duration::from_secs(60 * 60)),
+        }
+    })
+}
+
+/// Samples the latest temperature reading at a fixed interval into the history.
+#[embassy_executor::task]
+pub async fn telemetry(mut tempsensor_receiver: TempSensorDynReceiver) {
+    let mut ticker = Ticker::every(TELEMETRY_SAMPLE_INTERVAL);
+
+    loop {
+        ticker.next().await;
+
+        // Only count readings taken since the last sample, so a dead sensor shows up as gaps.
+        let sample = match tempsensor_receiver.try_changed() {
+            Some(reading) => reading.temperature.unwrap_or(f32::NAN),
+            None => f32::NAN,
+        };
+        critical_section::with(|cs| TEMP_HISTORY.borrow_ref_mut(cs).push(sample));
+    }
+}
@@ -1,4 +1,12 @@
-use alloc::boxed::Box;
+use crate::{
+    memlog::SharedLogger,
+    metrics::METRICS,
+    task::{
+        buzzer::{BuzzerChannel, NamedPattern},
+        supervisor::{self, Heartbeat},
+    },
+};
+use alloc::{boxed::Box, format};
 use embassy_sync::{blocking_mutex::raw::NoopRawMutex, watch};
 use embassy_time::{Duration, Instant, Timer};
 use esp_ds18b20::{Ds18b20, Ds18b20Error, Resolution, SensorData};
@@ -20,30 +28,56 @@ pub struct TemperatureReading {
     pub timestamp: Instant,
     pub temperature: Result<f32, Ds18b20Error>,
     pub retries: u8,
+    pub health: SensorHealth,
+}
+
+/// Running error statistics for the sensor.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct SensorHealth {
+    // Failed readings since the last good one.
+    pub consecutive_failures: u32,
+    // Checksum retries since boot.
+    pub total_retries: u32,
+    pub last_success: Option<Instant>,
 }
 
 // const DSPL_TEMP_SENSOR_ADDRESS: u64 = 0xF682AA490B646128;
-const DSPL_TEMP_SENSOR_ADDRESS: u64 = 0x60D7DB490B646128;
+pub(crate) const DSPL_TEMP_SENSOR_ADDRESS: u64 = 0x60D7DB490B646128;
 
 /// How long to wait between temperature readings.
 pub(crate) const TEMP_READING_INTERVAL: Duration = Duration::from_secs(5);
 
-/// How long a measurement takes (default resolution is 12 bit).
+/// The resolution the sensor is configured for.
+/// This must match the sensor's configuration register, which powers up at 12 bit
+/// unless a different default was written to its EEPROM.
+const DSPL_TEMP_SENSOR_RESOLUTION: Resolution = Resolution::Bits12;
+
+/// How long a measurement takes at the configured resolution.
 pub(crate) const SENSOR_MEASUREMENT_TIME: Duration =
-    Duration::from_millis(Resolution::Bits12.measurement_time_ms() as u64);
+    Duration::from_millis(DSPL_TEMP_SENSOR_RESOLUTION.measurement_time_ms() as u64);
 
 /// How many attempts to retry reading after a checksum error.
 pub(crate) const CHECKSUM_RETRIES: u8 = 3;
 
+/// Alert when the sensor has been unreadable for this long.
+const SENSOR_ALERT_AFTER: Duration = Duration::from_secs(60);
+
 #[embassy_executor::task]
 pub async fn temp_sensor(
     onewire_pin: gpio::AnyPin<'static>,
     tempsensor_sender: TempSensorDynSender,
+    buzzer_channel: BuzzerChannel,
+    memlog: SharedLogger,
 ) {
     let onewire_bus = OneWireBus::new(onewire_pin);
     let mut sensor = Ds18b20::new(DSPL_TEMP_SENSOR_ADDRESS, onewire_bus).unwrap();
 
+    let mut health = SensorHealth::default();
+    let boot = Instant::now();
+    let mut alerted = false;
+
     loop {
+        supervisor::check_in(Heartbeat::TempSensor);
         Timer::after(TEMP_READING_INTERVAL).await;
 
         let mut retries = 0;
@@ -54,7 +88,7 @@ pub async fn temp_sensor(
                 // Begin a measurement and wait for it to complete.
                 sensor.start_temp_measurement()?;
 
-                // 12bit resolution is the default, expects a 750ms wait time.
+                // 750ms at 12 bit, down to 94ms at 9 bit.
                 Timer::after(SENSOR_MEASUREMENT_TIME).await;
 
                 let data = sensor.read_sensor_data()?;
@@ -65,23 +99,56 @@ pub async fn temp_sensor(
 
             // Retry on checksum errors.
             match reading {
-                Err(Ds18b20Error::OneWireError(OneWireBusError::ChecksumFailed))
-                    if retries < CHECKSUM_RETRIES =>
-                {
-                    retries += 1;
-                    continue 'checksum_retries;
+                Err(Ds18b20Error::OneWireError(OneWireBusError::ChecksumFailed)) => {
+                    METRICS.onewire_checksum_failed();
+                    if retries < CHECKSUM_RETRIES {
+                        retries += 1;
+                        continue 'checksum_retries;
+                    }
+                    break 'checksum_retries reading;
                 }
-                _ => {
+                Err(_) => {
+                    METRICS.onewire_failed();
+                    break 'checksum_retries reading;
+                }
+                Ok(_) => {
                     break 'checksum_retries reading;
                 }
             }
         };
 
+        let now = Instant::now();
+        health.total_retries += u32::from(retries);
+        if sensor_reading.is_ok() {
+            if alerted {
+                alerted = false;
+                memlog.info(format!(
+                    "tempsensor: recovered after {} failed readings",
+                    health.consecutive_failures
+                ));
+            }
+            health.consecutive_failures = 0;
+            health.last_success = Some(now);
+        } else {
+            health.consecutive_failures += 1;
+            let failing_since = health.last_success.unwrap_or(boot);
+            if !alerted && now - failing_since >= SENSOR_ALERT_AFTER {
+                alerted = true;
+                memlog.error(format!(
+                    "tempsensor: unreadable for {}s ({} failed readings)",
+                    (now - failing_since).as_secs(),
+                    health.consecutive_failures
+                ));
+                buzzer_channel.send(NamedPattern::Alarm.into()).await;
+            }
+        }
+
         // Pull out the temperature and add a timestamp to our reading.
         let reading = TemperatureReading {
-            timestamp: Instant::now(),
+            timestamp: now,
             temperature: sensor_reading.map(|data| data.temperature),
             retries,
+            health,
         };
 
         tempsensor_sender.send(reading);
@@ -0,0 +1,120 @@
+//! Encrypted UDP commands, for LAN automations that want a single datagram per action.
+//!
+//! A packet is `nonce (12) | ciphertext | tag (16)`, sealed with ChaCha20-Poly1305 under
+//! the pre-shared `UDP_CONTROL_KEY`. The plaintext is a big-endian u64 counter followed by
+//! a JSON `PowerRequest`, e.g. `"Toggle"`. Senders must use a counter that always
+//! increases (a millisecond timestamp works). The highest counter accepted is kept in
+//! flash before the command runs, so a captured packet can't be replayed after a restart.
+//! Without the settings store, commands are refused.
+//!
+//! A command that can't run now (e.g. a power sequence is in progress) gets a plain-text
+//! datagram back with the reason.
+
+use crate::{
+    audit::{self, Source},
+    commands,
+    config::UDP_CONTROL_KEY,
+    memlog::SharedLogger,
+    settings::{self, Settings},
+    task::display_control::{DisplayPowerDynSender, PowerRequest},
+};
+use alloc::format;
+use chacha20poly1305::{AeadInPlace, ChaCha20Poly1305, KeyInit, Nonce, Tag};
+use embassy_net::udp::{PacketMetadata, UdpSocket};
+
+const UDP_CONTROL_PORT: u16 = 4950;
+const NONCE_LEN: usize = 12;
+const TAG_LEN: usize = 16;
+const COUNTER_LEN: usize = 8;
+const MAX_PACKET_LEN: usize = 128;
+
+/// Receives encrypted power requests over UDP.
+#[embassy_executor::task]
+pub async fn udp_control(
+    stack: embassy_net::Stack<'static>,
+    displaypower_sender: DisplayPowerDynSender,
+    settings: Settings,
+    memlog: SharedLogger,
+) {
+    let mut rx_meta = [PacketMetadata::EMPTY; 2];
+    let mut rx_buffer = [0u8; 2 * MAX_PACKET_LEN];
+    let mut tx_meta = [PacketMetadata::EMPTY; 1];
+    let mut tx_buffer = [0u8; 64];
+    let mut socket = UdpSocket::new(
+        stack,
+        &mut rx_meta,
+        &mut rx_buffer,
+        &mut tx_meta,
+        &mut tx_buffer,
+    );
+    if let Err(error) = socket.bind(UDP_CONTROL_PORT) {
+        memlog.warn(format!("udpctl: failed to bind socket: {error:?}"));
+        return;
+    }
+
+    let cipher = ChaCha20Poly1305::new(&UDP_CONTROL_KEY.into());
+    let mut last_counter: u64 = settings.load(settings::UDP_CONTROL_COUNTER).unwrap_or(0);
+    let mut packet = [0u8; MAX_PACKET_LEN];
+
+    loop {
+        let Ok((len, meta)) = socket.recv_from(&mut packet).await else {
+            // Truncated: larger than any valid packet.
+            continue;
+        };
+        if len < NONCE_LEN + COUNTER_LEN + TAG_LEN {
+            continue;
+        }
+
+        let (nonce, rest) = packet[..len].split_at_mut(NONCE_LEN);
+        let (plaintext, tag) = rest.split_at_mut(rest.len() - TAG_LEN);
+        let nonce = Nonce::from_slice(nonce);
+        let tag = Tag::from_slice(tag);
+        if cipher
+            .decrypt_in_place_detached(nonce, &[], plaintext, tag)
+            .is_err()
+        {
+            memlog.warn(format!("udpctl: bad packet from {}", meta.endpoint));
+            continue;
+        }
+
+        let (counter, command) = plaintext.split_at(COUNTER_LEN);
+        let counter = u64::from_be_bytes(counter.try_into().unwrap());
+        if counter <= last_counter {
+            memlog.warn(format!("udpctl: replayed packet from {}", meta.endpoint));
+            continue;
+        }
+        // Remember the counter before acting on it, or a reset in between would let
+        // the packet through again.
+        if let Err(error) = settings.store(settings::UDP_CONTROL_COUNTER, &counter) {
+            memlog.error(format!(
+                "udpctl: failed to store counter, refused: {error:?}"
+            ));
+            continue;
+        }
+        last_counter = counter;
+
+        match serde_json_core::from_slice::<PowerRequest>(command) {
+            Ok((request, _remainder)) => {
+                let sent = commands::check_power(request).and_then(|()| {
+                    audit::commanded(Source::UdpControl);
+                    commands::send_power(&displaypower_sender, request)
+                });
+                match sent {
+                    Ok(()) => memlog.info(format!("udpctl: {request:?} from {}", meta.endpoint)),
+                    Err(rejected) => {
+                        memlog.warn(format!(
+                            "udpctl: {request:?} from {} rejected, {}",
+                            meta.endpoint,
+                            rejected.as_str()
+                        ));
+                        let reply = socket.send_to(rejected.as_str().as_bytes(), meta.endpoint);
+                        if let Err(error) = reply.await {
+                            memlog.warn(format!("udpctl: failed to send rejection: {error:?}"));
+                        }
+                    }
+                }
+            }
+            Err(error) => memlog.warn(format!("udpctl: failed to deserialize command: {error}")),
+        }
+    }
+}